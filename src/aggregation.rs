@@ -0,0 +1,96 @@
+use std::{collections::BTreeMap, fmt};
+
+/// The ways in which several values can be combined into one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Aggregation {
+    Sum,
+    Mean,
+    Min,
+    Max,
+    First,
+    Last,
+}
+
+/// How a measure behaves along a dimension, which determines how values can
+/// be aggregated across it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Additivity {
+    /// Values can be summed, e.g. flows such as revenue.
+    #[default]
+    Additive,
+
+    /// Values cannot be summed and the last value represents the whole,
+    /// e.g. balances over time.
+    SemiAdditive,
+
+    /// Values cannot be summed, e.g. rates and prices.
+    NonAdditive,
+}
+
+impl Additivity {
+    /// Returns the aggregation used when rolling up across the dimension.
+    pub fn default_aggregation(self) -> Aggregation {
+        match self {
+            Additivity::Additive => Aggregation::Sum,
+            Additivity::SemiAdditive => Aggregation::Last,
+            Additivity::NonAdditive => Aggregation::Mean,
+        }
+    }
+
+    /// Indicates if `aggregation` makes sense across the dimension.
+    pub fn allows(self, aggregation: Aggregation) -> bool {
+        self == Additivity::Additive || aggregation != Aggregation::Sum
+    }
+}
+
+/// Returns the additivity of the result of an operation between operands
+/// with the additivity `lhs` and `rhs`, which keeps the additivity along
+/// each dimension that both agree on, e.g. the sum of two prices is still
+/// non-additive but the product of a price and a volume is additive.
+pub(crate) fn combine_additivity(
+    lhs: &BTreeMap<String, Additivity>,
+    rhs: &BTreeMap<String, Additivity>,
+) -> BTreeMap<String, Additivity> {
+    lhs.iter()
+        .filter(|(name, additivity)| rhs.get(*name) == Some(additivity))
+        .map(|(name, additivity)| (name.clone(), *additivity))
+        .collect()
+}
+
+impl fmt::Display for Additivity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Additivity::Additive => write!(f, "additive"),
+            Additivity::SemiAdditive => write!(f, "semi-additive"),
+            Additivity::NonAdditive => write!(f, "non-additive"),
+        }
+    }
+}
+
+impl Aggregation {
+    /// Aggregates `values` where each value is scaled by its weight.
+    ///
+    /// Returns `NaN` if `values` is empty.
+    pub(crate) fn aggregate_weighted(&self, values: impl Iterator<Item = (f64, f64)>) -> f64 {
+        let mut count = 0;
+        let mut acc: Option<f64> = None;
+        for (value, weight) in values {
+            let value = value * weight;
+            count += 1;
+            acc = Some(match (self, acc) {
+                (_, None) => value,
+                (Aggregation::Sum | Aggregation::Mean, Some(a)) => a + value,
+                (Aggregation::Min, Some(a)) => a.min(value),
+                (Aggregation::Max, Some(a)) => a.max(value),
+                (Aggregation::First, Some(a)) => a,
+                (Aggregation::Last, Some(_)) => value,
+            });
+        }
+        match (self, acc) {
+            (_, None) => f64::NAN,
+            (Aggregation::Mean, Some(a)) => a / count as f64,
+            (_, Some(a)) => a,
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! Contains iterators over the cells of `Data`.
+//!
+//! A cell is a single value together with its `Coordinate`, i.e. the value
+//! of each dimension that the data varies by.  Cells are visited in the
+//! order they are laid out in memory.
+
+use std::fmt;
+
+use crate::{
+    Data,
+    data::{Storage, Values},
+    granularity::{CellIndices, DimensionValue, Granularity},
+};
+
+/// The value of each dimension that a piece of data varies by for a single cell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coordinate<'a>(Vec<(&'a str, &'a DimensionValue)>);
+
+impl<'a> Coordinate<'a> {
+    /// Returns the value of the dimension `dimension_name`.
+    pub fn get(&self, dimension_name: &str) -> Option<&'a DimensionValue> {
+        self.0
+            .iter()
+            .find(|(name, _)| *name == dimension_name)
+            .map(|(_, value)| *value)
+    }
+
+    /// Returns the number of dimensions in the coordinate.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterates over the name and value of each dimension in layout order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &'a DimensionValue)> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+impl fmt::Display for Coordinate<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        for (idx, (name, value)) in self.0.iter().enumerate() {
+            if idx > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {}", name, value)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// An iterator over the cells of a piece of `Data`.
+pub struct Cells<'a> {
+    granularity: &'a Granularity,
+    indices: CellIndices,
+    values: std::slice::Iter<'a, f64>,
+}
+
+impl<'a> Iterator for Cells<'a> {
+    type Item = (Coordinate<'a>, f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.indices.advance()?;
+        let value = *self.values.next()?;
+        Some((Coordinate(self.granularity.coordinate(index)), value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.values.size_hint()
+    }
+}
+
+/// Allows the values of a piece of `Data` to be modified cell by cell.
+///
+/// The values are copied out when this is created, unless they are not
+/// shared, and written back to the data when it is dropped.
+pub struct CellsMut<'a> {
+    data: &'a mut Data,
+    values: Vec<f64>,
+}
+
+impl CellsMut<'_> {
+    /// Iterates over each cell with a mutable reference to its value.
+    pub fn iter_mut(&mut self) -> CellsIterMut<'_> {
+        CellsIterMut {
+            granularity: &self.data.granularity,
+            indices: self.data.granularity.cell_indices(),
+            values: self.values.iter_mut(),
+        }
+    }
+}
+
+impl<'a, 'b> IntoIterator for &'b mut CellsMut<'a> {
+    type Item = (Coordinate<'b>, &'b mut f64);
+    type IntoIter = CellsIterMut<'b>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+/// An iterator over the cells of a piece of `Data` with mutable values.
+pub struct CellsIterMut<'a> {
+    granularity: &'a Granularity,
+    indices: CellIndices,
+    values: std::slice::IterMut<'a, f64>,
+}
+
+impl<'a> Iterator for CellsIterMut<'a> {
+    type Item = (Coordinate<'a>, &'a mut f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.indices.advance()?;
+        let value = self.values.next()?;
+        Some((Coordinate(self.granularity.coordinate(index)), value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.values.size_hint()
+    }
+}
+
+impl Drop for CellsMut<'_> {
+    fn drop(&mut self) {
+        self.data.storage = Storage::Dense(Values::from(std::mem::take(&mut self.values)));
+    }
+}
+
+impl Data {
+    /// Iterates over every cell, yielding its coordinate and value.
+    pub fn iter_cells(&self) -> Cells<'_> {
+        Cells {
+            granularity: &self.granularity,
+            indices: self.granularity.cell_indices(),
+            values: self.values().values().iter(),
+        }
+    }
+
+    /// Allows every cell's value to be modified in place, see `CellsMut`.
+    pub fn iter_cells_mut(&mut self) -> CellsMut<'_> {
+        let storage = std::mem::replace(
+            &mut self.storage,
+            Storage::Dense(Values::from(Vec::<f64>::new())),
+        );
+        let values = storage.into_dense(&self.granularity);
+        let (_, buffer, _) = values.into_parts();
+        CellsMut {
+            data: self,
+            values: buffer.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::operators::mul;
+
+    use super::*;
+
+    #[test]
+    fn test_iter_cells() {
+        let headcount = Data::new(
+            "team".to_string(),
+            vec!["Ops", "Sales", "Tech"],
+            vec![2.0, 4.0, 6.0],
+        );
+        let salary = Data::new("site".to_string(), vec!["Lyon", "Oslo"], vec![3.0, 5.0]);
+        let cost = mul(&headcount, &salary);
+
+        let cells: Vec<_> = cost.iter_cells().collect();
+        assert_eq!(cells.len(), 6);
+
+        let (coordinate, value) = &cells[4];
+        assert_eq!(coordinate.get("site"), Some(&"Oslo".into()));
+        assert_eq!(coordinate.get("team"), Some(&"Sales".into()));
+        assert_eq!(*value, 20.0);
+        assert_eq!(coordinate.to_string(), "{site: Oslo, team: Sales}");
+    }
+
+    #[test]
+    fn test_iter_cells_mut() {
+        let mut data = Data::new("region".to_string(), vec!["EU", "US"], vec![1.0, 2.0]);
+        for (coordinate, value) in &mut data.iter_cells_mut() {
+            if coordinate.get("region") == Some(&"US".into()) {
+                *value = 5.0;
+            }
+        }
+        assert_eq!(data.values().values().to_vec(), vec![1.0, 5.0]);
+    }
+}
@@ -0,0 +1,413 @@
+//! Contains the valid combinations of dependent dimensions.
+//!
+//! Some dimensions only make sense in combination, e.g. product `A` is only
+//! sold in `EU` and `US`.  A piece of data can declare the combinations of
+//! values that are valid for a set of its dimensions, and the cells of any
+//! other combination are invalid.  Invalid cells are not stored, they read
+//! as `NaN`, and reductions ignore them.  Results of operators carry the
+//! valid combinations of both operands, so broadcasting across a dimension
+//! does not manufacture cells for combinations that cannot exist.
+//!
+//! Only the dimensions that the data varies by are checked, a combination
+//! is valid along the others if it is valid for any of their values.  So the
+//! combinations can name dimensions the data does not have yet, e.g. a price
+//! by product can declare the regions each product is sold in, which are
+//! checked once the price is broadcast across regions.
+
+use std::collections::BTreeSet;
+
+use crate::{
+    Data, DimensionValue, Granularity,
+    data::Storage,
+    error::{Error, Result},
+    sparse::SparseValues,
+};
+
+/// The valid combinations of the values of `dimensions`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct ValidCombinations {
+    pub(crate) dimensions: Vec<String>,
+    pub(crate) combinations: BTreeSet<Vec<DimensionValue>>,
+}
+
+/// The run-length and size of each dimension that is checked, along with
+/// whether each combination of the positions of their values is valid,
+/// indexed as if the dimensions were laid out in order.
+type Check = (Vec<(usize, usize)>, Vec<bool>);
+
+impl ValidCombinations {
+    /// Returns the combinations of the remaining dimensions that are valid
+    /// where `dimension_name` has the value `value`, see `without`.
+    pub(crate) fn at(&self, dimension_name: &str, value: &DimensionValue) -> Option<Self> {
+        let Some(idx) = self.dimensions.iter().position(|d| d == dimension_name) else {
+            return Some(self.clone());
+        };
+        let combinations = self
+            .combinations
+            .iter()
+            .filter(|combination| &combination[idx] == value)
+            .cloned()
+            .collect();
+        Self {
+            dimensions: self.dimensions.clone(),
+            combinations,
+        }
+        .without(dimension_name)
+    }
+
+    /// Returns the combinations of the remaining dimensions once
+    /// `dimension_name` is removed, or `None` if no dimension would remain.
+    fn without(&self, dimension_name: &str) -> Option<Self> {
+        let Some(idx) = self.dimensions.iter().position(|d| d == dimension_name) else {
+            return Some(self.clone());
+        };
+        if self.dimensions.len() == 1 {
+            return None;
+        }
+        let mut dimensions = self.dimensions.clone();
+        dimensions.remove(idx);
+        let combinations = self
+            .combinations
+            .iter()
+            .map(|combination| {
+                let mut combination = combination.clone();
+                combination.remove(idx);
+                combination
+            })
+            .collect();
+        Some(Self {
+            dimensions,
+            combinations,
+        })
+    }
+}
+
+impl Data {
+    /// Builder type API for declaring the valid combinations of the values
+    /// of the dimensions `dimension_names`, e.g.
+    /// `with_valid_combinations(&["product", "region"], [vec!["A", "EU"], vec!["A", "US"]])`.
+    ///
+    /// The cells of every other combination become invalid.  Returns an error
+    /// if a dimension is repeated, a value of a dimension the data has is
+    /// unknown or a combination has the wrong number of values.
+    pub fn with_valid_combinations<V: Into<DimensionValue>>(
+        mut self,
+        dimension_names: &[&str],
+        combinations: impl IntoIterator<Item = Vec<V>>,
+    ) -> Result<Self> {
+        for (idx, name) in dimension_names.iter().enumerate() {
+            if dimension_names[..idx].contains(name) {
+                return Err(Error::InvalidGranularity(format!(
+                    "dimension '{}' is repeated",
+                    name
+                )));
+            }
+        }
+        let mut valid = BTreeSet::new();
+        for combination in combinations {
+            if combination.len() != dimension_names.len() {
+                return Err(Error::LengthMismatch {
+                    expected: dimension_names.len(),
+                    actual: combination.len(),
+                });
+            }
+            let combination: Vec<DimensionValue> =
+                combination.into_iter().map(Into::into).collect();
+            for (name, value) in dimension_names.iter().zip(&combination) {
+                let values = self.granularity.dimension_values(name);
+                if values.is_some_and(|values| values.position(value).is_none()) {
+                    return Err(Error::InvalidDimensionValue {
+                        kind: value.kind(),
+                        value: value.to_string(),
+                    });
+                }
+            }
+            valid.insert(combination);
+        }
+        self.valid_combinations.push(ValidCombinations {
+            dimensions: dimension_names
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            combinations: valid,
+        });
+        Ok(self.apply_valid_combinations())
+    }
+
+    /// Returns whether the data declares any valid combinations, see
+    /// `with_valid_combinations`.
+    pub fn has_valid_combinations(&self) -> bool {
+        !self.valid_combinations.is_empty()
+    }
+
+    /// Returns a predicate over the offsets of the cells of the data that
+    /// indicates whether each cell is valid, or `None` if every cell is.
+    pub(crate) fn validity(&self) -> Option<impl Fn(usize) -> bool + use<>> {
+        validity(&self.granularity, &self.valid_combinations)
+    }
+
+    /// Drops the invalid cells of the data, storing the valid cells sparsely
+    /// with a `NaN` fill if any cell is invalid.
+    pub(crate) fn apply_valid_combinations(mut self) -> Data {
+        let Some(is_valid) = self.validity() else {
+            return self;
+        };
+        let cells: Vec<(usize, f64)> = match self.sparse() {
+            Some(sparse) if sparse.fill.is_nan() => {
+                if sparse.offsets.iter().all(|offset| is_valid(*offset)) {
+                    return self;
+                }
+                sparse
+                    .offsets
+                    .iter()
+                    .zip(&sparse.values)
+                    .filter(|(offset, _)| is_valid(**offset))
+                    .map(|(offset, value)| (*offset, *value))
+                    .collect()
+            }
+            _ => {
+                let values = self.values().values();
+                if (0..values.len()).all(&is_valid) {
+                    return self;
+                }
+                (0..values.len())
+                    .filter(|offset| is_valid(*offset))
+                    .map(|offset| (offset, values[offset]))
+                    .collect()
+            }
+        };
+        self.storage = Storage::Sparse(
+            SparseValues::from_cells(cells, f64::NAN),
+            Default::default(),
+        );
+        self
+    }
+
+    /// Restricts the result of an operation to the valid combinations of
+    /// every operand, `operands` holds those of each operand.
+    pub(crate) fn restricted_by(mut self, operands: &[&[ValidCombinations]]) -> Data {
+        for valid in combine_valid_combinations(operands) {
+            if !self.valid_combinations.contains(&valid) {
+                self.valid_combinations.push(valid);
+            }
+        }
+        self.apply_valid_combinations()
+    }
+
+    /// Declares each of `valid_combinations` in turn, for data that is read
+    /// back after being written.
+    #[cfg(feature = "serde")]
+    pub(crate) fn with_each_valid_combinations(
+        mut self,
+        valid_combinations: Vec<ValidCombinations>,
+    ) -> Result<Data> {
+        for valid in valid_combinations {
+            let dimensions: Vec<&str> = valid.dimensions.iter().map(String::as_str).collect();
+            self = self.with_valid_combinations(&dimensions, valid.combinations)?;
+        }
+        Ok(self)
+    }
+
+    /// Removes `dimension_name` from the valid combinations, for operations
+    /// that replace the values of the dimension.
+    pub(crate) fn forget_valid_combinations_of(mut self, dimension_name: &str) -> Data {
+        self.valid_combinations = self
+            .valid_combinations
+            .iter()
+            .filter_map(|valid| valid.without(dimension_name))
+            .collect();
+        self
+    }
+}
+
+/// Returns the valid combinations of the result of an operation, those of
+/// every operand without repeats, `operands` holds those of each operand.
+pub(crate) fn combine_valid_combinations(
+    operands: &[&[ValidCombinations]],
+) -> Vec<ValidCombinations> {
+    let mut combined: Vec<ValidCombinations> = Vec::new();
+    for valid in operands.iter().flat_map(|operand| operand.iter()) {
+        if !combined.contains(valid) {
+            combined.push(valid.clone());
+        }
+    }
+    combined
+}
+
+/// Returns a predicate over the offsets of the cells of `granularity` that
+/// indicates whether each cell is one of `valid_combinations`, or `None` if
+/// every cell is.
+///
+/// Whether each combination is valid is looked up once here, so checking a
+/// cell does not allocate.
+pub(crate) fn validity(
+    granularity: &Granularity,
+    valid_combinations: &[ValidCombinations],
+) -> Option<impl Fn(usize) -> bool + use<>> {
+    let mut checks: Vec<Check> = Vec::new();
+    for valid in valid_combinations {
+        let checked: Vec<usize> = (0..valid.dimensions.len())
+            .filter(|idx| granularity.maybe_varies_by(&valid.dimensions[*idx]))
+            .collect();
+        if checked.is_empty() {
+            continue;
+        }
+        let layout: Vec<(usize, usize)> = checked
+            .iter()
+            .map(|idx| {
+                let name = &valid.dimensions[*idx];
+                let size = granularity.dimension_values(name).unwrap().len();
+                (*granularity.run_length(name), size)
+            })
+            .collect();
+        let mut is_valid = vec![false; layout.iter().map(|(_, size)| size).product()];
+        for combination in &valid.combinations {
+            let positions = checked.iter().map(|idx| {
+                let name = &valid.dimensions[*idx];
+                let values = granularity.dimension_values(name).unwrap();
+                values.position(&combination[*idx])
+            });
+            let code = positions
+                .zip(&layout)
+                .try_fold(0, |code, (position, (_, size))| {
+                    Some(code * size + position?)
+                });
+            if let Some(code) = code {
+                is_valid[code] = true;
+            }
+        }
+        checks.push((layout, is_valid));
+    }
+    if checks.is_empty() {
+        return None;
+    }
+    Some(move |offset: usize| {
+        checks.iter().all(|(layout, is_valid)| {
+            let code = layout.iter().fold(0, |code, (run_length, size)| {
+                code * size + (offset / run_length) % size
+            });
+            is_valid[code]
+        })
+    })
+}
+
+/// Returns the valid combinations of `parts` concatenated along a dimension,
+/// the union of the combinations that every part declares for the same
+/// dimensions.
+pub(crate) fn concat_valid_combinations(parts: &[&Data]) -> Vec<ValidCombinations> {
+    let Some(first) = parts.first() else {
+        return Vec::new();
+    };
+    first
+        .valid_combinations
+        .iter()
+        .filter_map(|valid| {
+            let mut combinations = BTreeSet::new();
+            for part in parts {
+                let same = part
+                    .valid_combinations
+                    .iter()
+                    .find(|other| other.dimensions == valid.dimensions)?;
+                combinations.extend(same.combinations.iter().cloned());
+            }
+            Some(ValidCombinations {
+                dimensions: valid.dimensions.clone(),
+                combinations,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aggregation::Aggregation, operators::mul};
+
+    #[test]
+    fn test_invalid_cells_are_not_stored() {
+        // Product `A` is sold in `EU` and `US` and product `B` in `APAC`.
+        let sales = Data::from_rows(
+            &["product", "region"],
+            [
+                (vec!["A", "EU"], 1.0),
+                (vec!["A", "US"], 2.0),
+                (vec!["A", "APAC"], 3.0),
+                (vec!["B", "EU"], 4.0),
+                (vec!["B", "US"], 5.0),
+                (vec!["B", "APAC"], 6.0),
+            ],
+        )
+        .unwrap()
+        .with_valid_combinations(
+            &["product", "region"],
+            [vec!["A", "EU"], vec!["A", "US"], vec!["B", "APAC"]],
+        )
+        .unwrap();
+        assert!(sales.has_valid_combinations());
+        assert_eq!(sales.stored_len(), 3);
+        let total = sales.reduce("region", Aggregation::Sum).unwrap();
+        assert_eq!(total.values().values().to_vec(), vec![3.0, 6.0]);
+        let total = total.reduce("product", Aggregation::Sum).unwrap();
+        assert_eq!(total.values().value(0), 9.0);
+    }
+
+    #[test]
+    fn test_broadcasting_respects_valid_combinations() {
+        // Only varies by product, so every region is broadcast.
+        let price = Data::new("product".to_string(), vec!["A", "B"], vec![10.0, 20.0])
+            .with_valid_combinations(
+                &["product", "region"],
+                [vec!["A", "EU"], vec!["A", "US"], vec!["B", "APAC"]],
+            )
+            .unwrap();
+        assert_eq!(price.stored_len(), 2);
+        let volume = Data::new(
+            "region".to_string(),
+            vec!["EU", "US", "APAC"],
+            vec![1.0, 2.0, 3.0],
+        );
+
+        let revenue = mul(&price, &volume);
+        assert_eq!(revenue.stored_len(), 3);
+        let by_product = revenue.reduce("region", Aggregation::Sum).unwrap();
+        // "product" has the lowest cardinality so A comes first.
+        assert_eq!(by_product.values().values().to_vec(), vec![30.0, 60.0]);
+    }
+
+    #[test]
+    fn test_broadcasting_only_computes_valid_cells() {
+        // Declared in the opposite order to the layout of the product.
+        let price = Data::new("region".to_string(), vec!["EU", "US"], vec![10.0, 20.0])
+            .with_valid_combinations(
+                &["region", "product"],
+                [vec!["EU", "A"], vec!["US", "B"], vec!["US", "C"]],
+            )
+            .unwrap();
+        let volume = Data::new(
+            "product".to_string(),
+            vec!["A", "B", "C"],
+            vec![1.0, 2.0, 3.0],
+        );
+
+        let revenue = mul(&volume, &price);
+        let sparse = revenue.sparse().unwrap();
+        assert_eq!(sparse.values, vec![10.0, 40.0, 60.0]);
+        assert_eq!(revenue.valid_combinations.len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_valid_combinations() {
+        let data = Data::new("product".to_string(), vec!["A"], vec![1.0]);
+        let result = data
+            .clone()
+            .with_valid_combinations(&["product", "product"], [vec!["A", "A"]]);
+        assert!(matches!(result, Err(Error::InvalidGranularity(_))));
+        let result = data
+            .clone()
+            .with_valid_combinations(&["product"], [vec!["A", "EU"]]);
+        assert!(matches!(result, Err(Error::LengthMismatch { .. })));
+        let result = data.with_valid_combinations(&["product"], [vec!["Z"]]);
+        assert!(matches!(result, Err(Error::InvalidDimensionValue { .. })));
+    }
+}
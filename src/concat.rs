@@ -0,0 +1,229 @@
+//! Contains the concatenation of `Data` along a dimension.
+//!
+//! Each part covers a disjoint set of values of the dimension, e.g. actuals
+//! for 2023 and forecasts for 2024, and the same values of every other
+//! dimension.  The values of each part are interleaved according to the
+//! run-lengths of the result so no part has to be the outermost dimension.
+
+use crate::{
+    Data, DimensionValue, DimensionValues, Granularity,
+    combinations::concat_valid_combinations,
+    data::Values,
+    error::{Error, Result},
+    scenario::align_scenarios,
+};
+
+impl Data {
+    /// Concatenates `parts` along the dimension `dimension_name`, in order.
+    ///
+    /// The meta-data, e.g. the additivity, is taken from the first part.  The
+    /// valid combinations are the union of those of every part.
+    ///
+    /// Returns an error if a value of the dimension appears in more than one
+    /// part, if the parts vary by different dimensions or their units differ.
+    pub fn concat(parts: &[&Data], dimension_name: &str) -> Result<Data> {
+        // The dimension being concatenated has different values in each part
+        // by design, e.g. when scenarios are stacked.
+        let aligned = align_scenarios(parts, Some(dimension_name))?;
+        let parts: Vec<&Data> = aligned.iter().map(|part| &**part).collect();
+        let Some(first) = parts.first() else {
+            return Err(Error::InvalidGranularity(
+                "there must be at least one part to concatenate".to_string(),
+            ));
+        };
+        let mut dimension_values: Vec<DimensionValue> = Vec::new();
+        // The part and the position within it of each value of the dimension.
+        let mut sources = Vec::new();
+        for (part_idx, part) in parts.iter().enumerate() {
+            if !part.granularity.maybe_varies_by(dimension_name) {
+                return Err(Error::UnknownDimension(dimension_name.to_string()));
+            }
+            if part.unit != first.unit {
+                return Err(Error::UnitMismatch {
+                    lhs: describe_unit(first),
+                    rhs: describe_unit(part),
+                });
+            }
+            check_other_dimensions(first, part, dimension_name)?;
+
+            let values = part.granularity.dimension_values(dimension_name).unwrap();
+            for (position, value) in values.iter().enumerate() {
+                if dimension_values.contains(value) {
+                    return Err(Error::DuplicateCell(format!(
+                        "{{{}: {}}}",
+                        dimension_name, value
+                    )));
+                }
+                dimension_values.push(value.clone());
+                sources.push((part_idx, position));
+            }
+        }
+        let Some(dimension_values) = DimensionValues::try_new(dimension_values) else {
+            return Err(Error::InvalidGranularity(format!(
+                "dimension '{}' would have values of more than one kind",
+                dimension_name
+            )));
+        };
+
+        let dimensions = first
+            .granularity
+            .dimensions_with_flags()
+            .into_iter()
+            .map(|(name, values, varies)| {
+                if name == dimension_name {
+                    (name, dimension_values.clone(), true)
+                } else {
+                    (name, values, varies)
+                }
+            })
+            .collect();
+        let granularity = Granularity::from_dimensions(dimensions);
+        let idx = granularity.dimension_index(dimension_name).unwrap();
+
+        // The run-lengths of each part for every dimension but `dimension_name`,
+        // along with its run-length for `dimension_name`.
+        let layouts: Vec<_> = parts
+            .iter()
+            .map(|part| {
+                let mut run_lengths = part.granularity.run_lengths_for(&granularity);
+                run_lengths[idx] = 0;
+                (run_lengths, *part.granularity.run_length(dimension_name))
+            })
+            .collect();
+
+        let mut values = Vec::with_capacity(granularity.len());
+        granularity.for_each_cell(|index| {
+            let (part_idx, position) = sources[index[idx]];
+            let (run_lengths, run_length) = &layouts[part_idx];
+            let offset: usize = index.iter().zip(run_lengths).map(|(i, r)| i * r).sum();
+            values.push(parts[part_idx].values().values()[offset + position * run_length]);
+        });
+
+        let mut data =
+            Data::from_parts(granularity, Values::from(values)).with_metadata_from(first);
+        data.valid_combinations = concat_valid_combinations(&parts);
+        Ok(data.apply_valid_combinations())
+    }
+
+    /// Stacks `parts` under the new dimension `dimension_name`, where each
+    /// part is labelled by its value of the dimension, e.g. stacking
+    /// `actual` and `budget` under `scenario`.
+    ///
+    /// Returns an error if a part already has the dimension, see `concat`.
+    pub fn stack<V: Into<DimensionValue> + Clone>(
+        parts: &[(V, &Data)],
+        dimension_name: &str,
+    ) -> Result<Data> {
+        let mut labelled = Vec::with_capacity(parts.len());
+        for (label, part) in parts {
+            if part.granularity.dimension_index(dimension_name).is_some() {
+                return Err(Error::InvalidGranularity(format!(
+                    "the parts to stack already have the dimension '{}'",
+                    dimension_name
+                )));
+            }
+            // The new dimension only has one value so adding it does not change
+            // the layout of the values.
+            let mut dimensions = part.granularity.dimensions_with_flags();
+            dimensions.push((
+                dimension_name.to_string(),
+                DimensionValues::new(vec![label.clone().into()]),
+                true,
+            ));
+            let granularity = Granularity::from_dimensions(dimensions);
+            labelled.push(
+                Data::from_parts(granularity, part.values().clone()).with_metadata_from(part),
+            );
+        }
+        let labelled: Vec<&Data> = labelled.iter().collect();
+        Data::concat(&labelled, dimension_name)
+    }
+}
+
+/// Checks that `part` has the same dimensions as `first`, other than
+/// `dimension_name`.
+fn check_other_dimensions(first: &Data, part: &Data, dimension_name: &str) -> Result<()> {
+    let others = |data: &Data| -> Vec<(String, DimensionValues, bool)> {
+        let mut dimensions = data.granularity.dimensions_with_flags();
+        dimensions.retain(|(name, _, varies)| *varies && name != dimension_name);
+        dimensions.sort_by(|(l, _, _), (r, _, _)| l.cmp(r));
+        dimensions
+    };
+    let (expected, actual) = (others(first), others(part));
+    if expected.len() != actual.len() || expected.iter().zip(&actual).any(|(l, r)| l.0 != r.0) {
+        return Err(Error::InvalidGranularity(format!(
+            "the parts to concatenate along '{}' vary by different dimensions",
+            dimension_name
+        )));
+    }
+    match expected.iter().zip(&actual).find(|(l, r)| l.1 != r.1) {
+        Some(((name, _, _), _)) => Err(Error::ConflictingDimensionValues(name.clone())),
+        None => Ok(()),
+    }
+}
+
+fn describe_unit(data: &Data) -> String {
+    data.unit()
+        .map(|unit| unit.to_string())
+        .unwrap_or_else(|| "none".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Period;
+
+    #[test]
+    fn test_concat_interleaves_values() {
+        let actuals = Data::from_rows::<DimensionValue>(
+            &["region", "time"],
+            [
+                (vec!["EU".into(), Period::yearly(2023).into()], 1.0),
+                (vec!["US".into(), Period::yearly(2023).into()], 2.0),
+            ],
+        )
+        .unwrap();
+        let forecast = Data::from_rows::<DimensionValue>(
+            &["region", "time"],
+            [
+                (vec!["EU".into(), Period::yearly(2024).into()], 3.0),
+                (vec!["US".into(), Period::yearly(2024).into()], 4.0),
+                (vec!["EU".into(), Period::yearly(2025).into()], 5.0),
+                (vec!["US".into(), Period::yearly(2025).into()], 6.0),
+            ],
+        )
+        .unwrap();
+
+        let data = Data::concat(&[&actuals, &forecast], "time").unwrap();
+        let years = data.granularity().dimension_values("time").unwrap();
+        assert_eq!(years.len(), 3);
+        assert_eq!(years.get(0), Some(&Period::yearly(2023).into()));
+        assert_eq!(
+            data.values().values().to_vec(),
+            vec![1.0, 3.0, 5.0, 2.0, 4.0, 6.0]
+        );
+
+        let overlapping = Data::concat(&[&forecast, &forecast], "time");
+        assert!(matches!(overlapping, Err(Error::DuplicateCell(_))));
+
+        // Years as integers cannot be mixed with yearly periods.
+        let periods = Data::new("time".to_string(), vec![Period::yearly(2023)], vec![1.0]);
+        let integers = Data::new("time".to_string(), vec![2024], vec![2.0]);
+        let mixed = Data::concat(&[&periods, &integers], "time");
+        assert!(matches!(mixed, Err(Error::InvalidGranularity(_))));
+    }
+
+    #[test]
+    fn test_stack() {
+        let actual = Data::new("region".to_string(), vec!["EU", "US"], vec![1.0, 2.0]);
+        let budget = Data::new("region".to_string(), vec!["EU", "US"], vec![3.0, 4.0]);
+
+        let data = Data::stack(&[("actual", &actual), ("budget", &budget)], "scenario").unwrap();
+        assert!(data.granularity().varies_by("scenario"));
+        assert_eq!(data.values().values().to_vec(), vec![1.0, 3.0, 2.0, 4.0]);
+
+        let other = Data::new("product".to_string(), vec!["A", "B"], vec![1.0, 2.0]);
+        let result = Data::stack(&[("actual", &actual), ("budget", &other)], "scenario");
+        assert!(matches!(result, Err(Error::InvalidGranularity(_))));
+    }
+}
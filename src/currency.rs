@@ -0,0 +1,276 @@
+//! Contains the conversion of `Data` between currencies.
+//!
+//! The rates are themselves `Data` that vary by the currency dimension and,
+//! optionally, by time.  Each rate is the amount of the target currency
+//! that one unit of the currency is worth, and the target currency itself
+//! converts at `1.0` unless a rate is given for it.
+
+use std::collections::HashSet;
+
+use crate::{
+    Data, DimensionValue, DimensionValues, Granularity,
+    aggregation::Aggregation,
+    data::Values,
+    error::{Error, Result},
+    scenario::align_scenarios,
+    unit::Unit,
+};
+
+/// Options for converting between currencies, see `Data::convert_currency`.
+#[derive(Debug, Clone)]
+pub struct CurrencyConversion {
+    /// The name of the currency dimension, `currency` by default.
+    currency_dimension: String,
+
+    /// The name of the time dimension, `time` by default.
+    time_dimension: String,
+
+    /// Whether to keep the currency dimension, with the target currency as
+    /// its only value, rather than dropping it.
+    keep_currency_dimension: bool,
+}
+
+impl Default for CurrencyConversion {
+    fn default() -> Self {
+        Self {
+            currency_dimension: "currency".to_string(),
+            time_dimension: "time".to_string(),
+            keep_currency_dimension: false,
+        }
+    }
+}
+
+impl CurrencyConversion {
+    /// Builder type API for setting the name of the currency dimension.
+    pub fn currency_dimension(mut self, dimension_name: &str) -> Self {
+        self.currency_dimension = dimension_name.to_string();
+        self
+    }
+
+    /// Builder type API for setting the name of the time dimension.
+    pub fn time_dimension(mut self, dimension_name: &str) -> Self {
+        self.time_dimension = dimension_name.to_string();
+        self
+    }
+
+    /// Builder type API for relabelling the currency dimension as the target
+    /// currency rather than dropping it.
+    pub fn keep_currency_dimension(mut self) -> Self {
+        self.keep_currency_dimension = true;
+        self
+    }
+}
+
+impl Data {
+    /// Converts data that varies by currency into the currency `target`
+    /// using `rates`, summing the converted values across the currency
+    /// dimension.
+    ///
+    /// Returns `Error::MissingRates` listing every currency, and period if
+    /// the rates vary by time, that the data has but the rates do not.
+    pub fn convert_currency(
+        &self,
+        rates: &Data,
+        target: &str,
+        options: CurrencyConversion,
+    ) -> Result<Data> {
+        let operands = align_scenarios(&[self, rates], None)?;
+        let (data, rates) = (&*operands[0], &*operands[1]);
+        let currency = options.currency_dimension.as_str();
+        let time = options.time_dimension.as_str();
+        for operand in [data, rates] {
+            if !operand.granularity.maybe_varies_by(currency) {
+                return Err(Error::UnknownDimension(currency.to_string()));
+            }
+        }
+        if let Some((name, _)) = rates
+            .granularity
+            .dimensions()
+            .find(|(name, _)| *name != currency && *name != time)
+        {
+            return Err(Error::InvalidGranularity(format!(
+                "the rates vary by '{}' but may only vary by '{}' and '{}'",
+                name, currency, time
+            )));
+        }
+        let by_time = rates.granularity.maybe_varies_by(time);
+        if by_time && !data.granularity.maybe_varies_by(time) {
+            return Err(Error::InvalidTimeDimension {
+                dimension: time.to_string(),
+                reason: "the rates vary by it but the data does not".to_string(),
+            });
+        }
+
+        // The offset into `rates` of each value of a dimension of `data`.
+        let offsets = |name: &str| -> Vec<Option<usize>> {
+            let run_length = *rates.granularity.run_length(name);
+            let rate_values = rates.granularity.dimension_values(name).unwrap();
+            data.granularity
+                .dimension_values(name)
+                .unwrap()
+                .iter()
+                .map(|value| rate_values.position(value).map(|idx| idx * run_length))
+                .collect()
+        };
+        let currency_offsets = offsets(currency);
+        let time_offsets = if by_time { offsets(time) } else { Vec::new() };
+        let currency_idx = data.granularity.dimension_index(currency).unwrap();
+        let time_idx = data.granularity.dimension_index(time);
+        let target_value = DimensionValue::from(target);
+
+        let source = data.values().values();
+        let rate_values = rates.values().values();
+        let mut missing = HashSet::new();
+        let mut values = Vec::with_capacity(source.len());
+        data.granularity.for_each_cell(|index| {
+            let (c, t) = (index[currency_idx], time_idx.map(|idx| index[idx]));
+            let offset = match t {
+                Some(t) if by_time => currency_offsets[c].zip(time_offsets[t]).map(|(c, t)| c + t),
+                _ => currency_offsets[c],
+            };
+            let rate = match offset.map(|offset| rate_values[offset]) {
+                Some(rate) if !rate.is_nan() => rate,
+                _ if data.granularity.dimension_values(currency).unwrap().get(c)
+                    == Some(&target_value) =>
+                {
+                    1.0
+                }
+                _ => {
+                    missing.insert((c, t.filter(|_| by_time)));
+                    f64::NAN
+                }
+            };
+            values.push(source[values.len()] * rate);
+        });
+
+        if !missing.is_empty() {
+            let describe = |name: &str, idx: usize| {
+                let values = data.granularity.dimension_values(name).unwrap();
+                format!("{}: {}", name, values.get(idx).unwrap())
+            };
+            let mut missing: Vec<_> = missing.into_iter().collect();
+            missing.sort();
+            let missing = missing
+                .into_iter()
+                .map(|(c, t)| match t {
+                    Some(t) => format!("{{{}, {}}}", describe(currency, c), describe(time, t)),
+                    None => format!("{{{}}}", describe(currency, c)),
+                })
+                .collect();
+            return Err(Error::MissingRates(missing));
+        }
+
+        let converted = Data::from_parts(data.granularity.clone(), Values::from(values))
+            .with_metadata_from(data)
+            .with_unit(Unit::base(target));
+        let reduced = converted.reduce(currency, Aggregation::Sum)?;
+        if !options.keep_currency_dimension {
+            return Ok(reduced);
+        }
+
+        // The currency dimension only has one value so the layout of the
+        // values is unchanged by adding it back.
+        let dimensions = reduced
+            .granularity
+            .dimensions_with_flags()
+            .into_iter()
+            .map(|(name, values, varies)| {
+                if name == currency {
+                    (name, DimensionValues::new(vec![target_value.clone()]), true)
+                } else {
+                    (name, values, varies)
+                }
+            })
+            .collect();
+        let granularity = Granularity::from_dimensions(dimensions);
+        Ok(Data::from_parts(granularity, reduced.values().clone()).with_metadata_from(&converted))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Period;
+
+    #[test]
+    fn test_convert_with_monthly_rates() {
+        let sales = Data::from_rows::<DimensionValue>(
+            &["currency", "time"],
+            [
+                (vec!["EUR".into(), Period::monthly(2024, 1).into()], 100.0),
+                (vec!["EUR".into(), Period::monthly(2024, 2).into()], 200.0),
+                (vec!["GBP".into(), Period::monthly(2024, 1).into()], 10.0),
+                (vec!["GBP".into(), Period::monthly(2024, 2).into()], 20.0),
+                (vec!["USD".into(), Period::monthly(2024, 1).into()], 1.0),
+                (vec!["USD".into(), Period::monthly(2024, 2).into()], 2.0),
+            ],
+        )
+        .unwrap();
+        let rates = Data::from_rows::<DimensionValue>(
+            &["currency", "time"],
+            [
+                (vec!["GBP".into(), Period::monthly(2024, 2).into()], 0.5),
+                (vec!["EUR".into(), Period::monthly(2024, 1).into()], 1.5),
+                (vec!["EUR".into(), Period::monthly(2024, 2).into()], 1.25),
+                (vec!["GBP".into(), Period::monthly(2024, 1).into()], 2.0),
+            ],
+        )
+        .unwrap();
+
+        let usd = sales
+            .convert_currency(&rates, "USD", CurrencyConversion::default())
+            .unwrap();
+        assert!(!usd.granularity().varies_by("currency"));
+        assert_eq!(usd.values().values().to_vec(), vec![171.0, 262.0]);
+        assert_eq!(usd.unit(), Some(&Unit::base("USD")));
+
+        let kept = sales
+            .convert_currency(
+                &rates,
+                "USD",
+                CurrencyConversion::default().keep_currency_dimension(),
+            )
+            .unwrap();
+        assert_eq!(
+            kept.granularity().dimension_values("currency").unwrap(),
+            &DimensionValues::new(vec!["USD".into()])
+        );
+        assert_eq!(kept.values().values().to_vec(), vec![171.0, 262.0]);
+    }
+
+    #[test]
+    fn test_reports_missing_rates() {
+        let sales = Data::from_rows::<DimensionValue>(
+            &["currency", "time"],
+            [
+                (vec!["EUR".into(), Period::monthly(2024, 3).into()], 40.0),
+                (vec!["EUR".into(), Period::monthly(2024, 4).into()], 80.0),
+                (vec!["GBP".into(), Period::monthly(2024, 3).into()], 4.0),
+                (vec!["GBP".into(), Period::monthly(2024, 4).into()], 8.0),
+                (vec!["USD".into(), Period::monthly(2024, 3).into()], 3.0),
+                (vec!["USD".into(), Period::monthly(2024, 4).into()], 6.0),
+            ],
+        )
+        .unwrap();
+        let rates = Data::from_rows::<DimensionValue>(
+            &["currency", "time"],
+            [(vec!["EUR".into(), Period::monthly(2024, 3).into()], 1.1)],
+        )
+        .unwrap();
+
+        let error = sales
+            .convert_currency(&rates, "USD", CurrencyConversion::default())
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "No rate for {currency: EUR, time: 2024-04}, {currency: GBP, time: 2024-03}, \
+             {currency: GBP, time: 2024-04}"
+        );
+
+        let flat = Data::new("currency".to_string(), vec!["EUR", "GBP"], vec![1.5, 0.5]);
+        let usd = sales
+            .convert_currency(&flat, "USD", CurrencyConversion::default())
+            .unwrap();
+        assert_eq!(usd.values().values().to_vec(), vec![65.0, 130.0]);
+    }
+}
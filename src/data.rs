@@ -1,65 +1,326 @@
-use crate::{granularity::Granularity, query::Query};
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::OnceLock,
+};
+
+use indexmap::IndexSet;
+
+use crate::{
+    aggregation::Additivity,
+    combinations::ValidCombinations,
+    error::{Error, Result},
+    granularity::{DimensionValue, DimensionValues, Granularity},
+    query::Query,
+    scenario::ScenarioValues,
+    sparse::SparseValues,
+    unit::Unit,
+};
 
 use arrow_array::{PrimitiveArray, types::Float64Type};
 
 pub(crate) type Values = PrimitiveArray<Float64Type>;
 
+/// How the values of a piece of `Data` are stored.
+#[derive(Clone)]
+pub(crate) enum Storage {
+    /// A value for every cell.
+    Dense(Values),
+
+    /// Only the cells that differ from the fill value, along with the dense
+    /// values once they have been needed.
+    Sparse(SparseValues, OnceLock<Values>),
+
+    /// The values without the scenario dimension and the overrides of each
+    /// scenario, along with the values of every scenario once they have been
+    /// needed, see `Data::with_override`.
+    Scenarios(Box<ScenarioValues>, OnceLock<Values>),
+}
+
+impl Storage {
+    /// Returns the dense values of the cells of `granularity`.
+    pub(crate) fn into_dense(self, granularity: &Granularity) -> Values {
+        match self {
+            Storage::Dense(values) => values,
+            Storage::Sparse(sparse, dense) => dense
+                .into_inner()
+                .unwrap_or_else(|| sparse.to_dense(granularity.len())),
+            Storage::Scenarios(scenarios, dense) => dense
+                .into_inner()
+                .unwrap_or_else(|| scenarios.to_dense(granularity)),
+        }
+    }
+}
+
 /// This is the main type used to model data of varying
 /// granularity.
+#[derive(Clone)]
 pub struct Data {
     /// Holds the meta-data so we know how to interpret the
     /// `values`.
     pub(crate) granularity: Granularity,
 
-    /// Holds the actual values.
-    pub(crate) values: Values,
+    /// Holds the actual values, see `Storage`.
+    pub(crate) storage: Storage,
+
+    /// Holds how the values behave along each dimension, dimensions that
+    /// are not listed are additive.
+    pub(crate) additivity: BTreeMap<String, Additivity>,
+
+    /// Holds the unit of the values, if known.
+    pub(crate) unit: Option<Unit>,
+
+    /// Holds the valid combinations of the values of dependent dimensions,
+    /// see `Data::with_valid_combinations`.
+    pub(crate) valid_combinations: Vec<ValidCombinations>,
 }
 
 impl Data {
+    /// Creates a new piece of data from a granularity and matching values,
+    /// without any further meta-data.
+    pub(crate) fn from_parts(granularity: Granularity, values: Values) -> Self {
+        Self {
+            granularity,
+            storage: Storage::Dense(values),
+            additivity: BTreeMap::new(),
+            unit: None,
+            valid_combinations: Vec::new(),
+        }
+    }
+
+    /// Copies the meta-data, other than the granularity, from `other`.
+    pub(crate) fn with_metadata_from(mut self, other: &Data) -> Self {
+        self.additivity = other.additivity.clone();
+        self.unit = other.unit.clone();
+        self.valid_combinations = other.valid_combinations.clone();
+        self
+    }
+
     /// Creates a new piece of data that contains a single dimension.
-    pub fn new(dimension_name: String, dimension_values: Vec<String>, values: Vec<f64>) -> Self {
+    pub fn new<V: Into<DimensionValue>>(
+        dimension_name: String,
+        dimension_values: Vec<V>,
+        values: Vec<f64>,
+    ) -> Self {
         let granularity = Granularity::new(dimension_name, dimension_values);
         let values = PrimitiveArray::<Float64Type>::from(values);
 
-        Self {
-            granularity,
-            values,
-        }
+        Self::from_parts(granularity, values)
     }
 
     /// Creates a new piece of data that contains a single dimension from an iterator.
-    pub fn new_from_iter(
+    pub fn new_from_iter<V: Into<DimensionValue>>(
         dimension_name: String,
-        iter: impl Iterator<Item = (String, f64)>,
+        iter: impl Iterator<Item = (V, f64)>,
     ) -> Self {
-        let (dimension_values, values) = iter.unzip();
+        let (dimension_values, values): (Vec<V>, Vec<f64>) = iter.unzip();
         Self::new(dimension_name, dimension_values, values)
     }
 
+    /// Creates a new piece of data from rows in "long" format, i.e. one row per
+    /// cell holding the value of each of `dimension_names` and the cell's value.
+    ///
+    /// The values of each dimension are ordered by their first appearance.
+    /// Cells that do not appear in `rows` are `NaN`.
+    pub fn from_rows<V: Into<DimensionValue>>(
+        dimension_names: &[&str],
+        rows: impl IntoIterator<Item = (Vec<V>, f64)>,
+    ) -> Result<Self> {
+        let (granularity, cells) = Self::cells_from_rows(dimension_names, rows)?;
+        let mut values = vec![f64::NAN; granularity.len()];
+        for (offset, value) in cells {
+            values[offset] = value;
+        }
+
+        Ok(Self::from_parts(granularity, Values::from(values)))
+    }
+
+    /// Reads rows in "long" format, see `from_rows`, returning the granularity
+    /// along with the offset and value of each cell in the order of `rows`.
+    pub(crate) fn cells_from_rows<V: Into<DimensionValue>>(
+        dimension_names: &[&str],
+        rows: impl IntoIterator<Item = (Vec<V>, f64)>,
+    ) -> Result<(Granularity, Vec<(usize, f64)>)> {
+        let mut dimension_values = vec![IndexSet::new(); dimension_names.len()];
+        let mut cells = Vec::new();
+        for (coordinate, value) in rows {
+            if coordinate.len() != dimension_names.len() {
+                return Err(Error::LengthMismatch {
+                    expected: dimension_names.len(),
+                    actual: coordinate.len(),
+                });
+            }
+            let index: Vec<usize> = coordinate
+                .into_iter()
+                .zip(dimension_values.iter_mut())
+                .map(|(v, values)| values.insert_full(v.into()).0)
+                .collect();
+            cells.push((index, value));
+        }
+
+        let mut dimensions = Vec::with_capacity(dimension_names.len());
+        for (name, values) in dimension_names.iter().zip(dimension_values) {
+            let values = DimensionValues::try_new(values.into_iter().collect());
+            match values {
+                Some(values) if !dimensions.iter().any(|(n, _, _)| n == name) => {
+                    dimensions.push((name.to_string(), values, true));
+                }
+                _ => {
+                    return Err(Error::InvalidGranularity(format!(
+                        "dimension '{}' is repeated or has values of more than one kind",
+                        name
+                    )));
+                }
+            }
+        }
+        let granularity = Granularity::from_dimensions(dimensions);
+
+        let run_lengths: Vec<usize> = dimension_names
+            .iter()
+            .map(|name| *granularity.run_length(name))
+            .collect();
+        let mut filled = HashSet::new();
+        let mut offsets = Vec::with_capacity(cells.len());
+        for (index, value) in cells {
+            let offset: usize = index.iter().zip(&run_lengths).map(|(i, r)| i * r).sum();
+            if !filled.insert(offset) {
+                let coordinate = dimension_names
+                    .iter()
+                    .zip(&index)
+                    .map(|(name, i)| {
+                        let values = granularity.dimension_values(name).unwrap();
+                        format!("{}: {}", name, values.get(*i).unwrap())
+                    })
+                    .collect::<Vec<_>>();
+                return Err(Error::DuplicateCell(format!(
+                    "{{{}}}",
+                    coordinate.join(", ")
+                )));
+            }
+            offsets.push((offset, value));
+        }
+
+        Ok((granularity, offsets))
+    }
+
     pub fn granularity(&self) -> &Granularity {
         &self.granularity
     }
 
+    /// Returns the values of every cell, densifying sparse data and
+    /// expanding overrides the first time they are needed.
     pub fn values(&self) -> &Values {
-        &self.values
+        match &self.storage {
+            Storage::Dense(values) => values,
+            Storage::Sparse(sparse, dense) => {
+                dense.get_or_init(|| sparse.to_dense(self.granularity.len()))
+            }
+            Storage::Scenarios(scenarios, dense) => {
+                dense.get_or_init(|| scenarios.to_dense(&self.granularity))
+            }
+        }
     }
 
-    pub fn query(&self, query: &Query) -> Self {
-        assert_eq!(self.granularity.size(), 1);
-        let data_offset = self.granularity.data_offset(query);
-        let values = self.values.slice(data_offset, 1);
-        let mut granularity = self.granularity.clone();
-        granularity.drop(&query.dimension_name);
-        Self {
-            granularity,
-            values,
+    /// Builder type API for declaring how the values behave along the
+    /// dimension `dimension_name`, see `Additivity`.
+    pub fn with_additivity(mut self, dimension_name: &str, additivity: Additivity) -> Self {
+        self.additivity
+            .insert(dimension_name.to_string(), additivity);
+        self
+    }
+
+    /// Returns how the values behave along the dimension `dimension_name`.
+    pub fn additivity(&self, dimension_name: &str) -> Additivity {
+        self.additivity
+            .get(dimension_name)
+            .copied()
+            .unwrap_or(Additivity::Additive)
+    }
+
+    /// Expands the data so that it physically varies by every dimension that
+    /// `granularity` varies by, copying values as required.
+    ///
+    /// Returns an error if `granularity` does not vary by every dimension that
+    /// the data varies by, or if the values of a dimension differ.
+    pub fn expand_to(&self, granularity: &Granularity) -> Result<Data> {
+        self.granularity.check_expands_to(granularity)?;
+        Ok(self.gather(granularity))
+    }
+
+    /// Drops every dimension that the data does not actually vary over, i.e.
+    /// where the values are identical across the dimension, compacting the
+    /// values to match.
+    pub fn squeeze(&self) -> Data {
+        let source = self.values().values();
+        let run_lengths = self.granularity.flags().run_lengths();
+        let mut dimensions = self.granularity.dimensions_with_flags();
+
+        for (idx, (_, values, varies)) in dimensions.iter_mut().enumerate() {
+            if !*varies {
+                continue;
+            }
+            let run_length = run_lengths[idx];
+            // A dimension without values has no cells to be constant across.
+            let mut constant = !values.is_empty();
+            self.granularity.for_each_cell(|index| {
+                if !constant || index[idx] != 0 {
+                    return;
+                }
+                let offset: usize = index.iter().zip(run_lengths).map(|(i, r)| i * r).sum();
+                let first = source[offset];
+                constant = (1..values.len()).all(|i| {
+                    let value = source[offset + i * run_length];
+                    value == first || (value.is_nan() && first.is_nan())
+                });
+            });
+            if constant {
+                *varies = false;
+            }
         }
+
+        self.gather(&Granularity::from_dimensions(dimensions))
+    }
+
+    /// Creates a new piece of data with `granularity` by reading the value
+    /// for each cell from `self`.
+    ///
+    /// Dimensions that `granularity` varies by but `self` doesn't are
+    /// broadcast, dimensions that `self` varies by but `granularity` doesn't
+    /// are read at their first value.
+    fn gather(&self, granularity: &Granularity) -> Data {
+        let run_lengths = self.granularity.run_lengths_for(granularity);
+        let source = self.values().values();
+        let mut values = Vec::with_capacity(granularity.len());
+        granularity.for_each_cell(|index| {
+            let offset: usize = index
+                .iter()
+                .zip(run_lengths.iter())
+                .map(|(i, run_length)| i * run_length)
+                .sum();
+            values.push(source[offset]);
+        });
+
+        Self::from_parts(granularity.clone(), Values::from(values))
+            .with_metadata_from(self)
+            .apply_valid_combinations()
+    }
+
+    /// Returns the cells where the dimension of `query` has its value, see
+    /// `slice`.
+    ///
+    /// # Panics
+    ///
+    /// If the data does not have the dimension or the value.
+    pub fn query(&self, query: &Query) -> Self {
+        self.slice(&query.dimension_name, query.dimension_value.clone())
+            .expect("The queried dimension and value exist")
+            .to_data()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Error;
 
     #[test]
     fn test_single_element() {
@@ -79,4 +340,120 @@ mod tests {
         assert!(data.granularity.varies_by("test"));
         assert_eq!(data.granularity.run_length("test"), &1);
     }
+
+    #[test]
+    fn test_expand_to() {
+        let region = Data::new("region".to_string(), vec!["EU", "US"], vec![1.0, 2.0]);
+        let product = Data::new("product".to_string(), vec!["A", "B", "C"], vec![0.0; 3]);
+        let target = region.granularity().broadcast(product.granularity());
+
+        let expanded = region.expand_to(&target).unwrap();
+        assert!(expanded.granularity.varies_by("region"));
+        assert!(expanded.granularity.varies_by("product"));
+        assert_eq!(
+            expanded.values().values().to_vec(),
+            vec![1.0, 1.0, 1.0, 2.0, 2.0, 2.0]
+        );
+    }
+
+    #[test]
+    fn test_expand_to_missing_dimension() {
+        let region = Data::new("region".to_string(), vec!["EU", "US"], vec![1.0, 2.0]);
+        let product = Data::new("product".to_string(), vec!["A", "B", "C"], vec![0.0; 3]);
+
+        let result = region.expand_to(product.granularity());
+        assert!(matches!(result, Err(Error::MissingDimension(name)) if name == "region"));
+    }
+
+    #[test]
+    fn test_squeeze() {
+        let region = Data::new("region".to_string(), vec!["EU", "US"], vec![1.0, 2.0]);
+        let product = Data::new("product".to_string(), vec!["A", "B", "C"], vec![0.0; 3]);
+        let target = region.granularity().broadcast(product.granularity());
+
+        let squeezed = region.expand_to(&target).unwrap().squeeze();
+        assert!(squeezed.granularity.varies_by("region"));
+        assert!(!squeezed.granularity.varies_by("product"));
+        assert_eq!(squeezed.values().values().to_vec(), vec![1.0, 2.0]);
+    }
+
+    #[test]
+    fn test_squeeze_empty_dimension() {
+        let data = Data::new("region".to_string(), Vec::<&str>::new(), vec![]);
+        let squeezed = data.squeeze();
+        assert!(squeezed.granularity.varies_by("region"));
+        assert!(squeezed.values().is_empty());
+    }
+
+    #[test]
+    fn test_squeeze_keeps_varied_dimensions() {
+        let stock = Data::from_rows(
+            &["size", "colour"],
+            [
+                (vec!["S", "red"], 4.0),
+                (vec!["S", "blue"], 0.0),
+                (vec!["M", "red"], 7.0),
+                (vec!["M", "blue"], 1.0),
+            ],
+        )
+        .unwrap();
+
+        let squeezed = stock.squeeze();
+        assert!(squeezed.granularity == stock.granularity);
+        assert_eq!(squeezed.values().len(), 4);
+    }
+
+    #[test]
+    fn test_from_rows() {
+        let rows = vec![
+            (vec!["EU", "A"], 1.0),
+            (vec!["EU", "B"], 2.0),
+            (vec!["EU", "C"], 3.0),
+            (vec!["US", "A"], 4.0),
+            (vec!["US", "C"], 6.0),
+        ];
+        let data = Data::from_rows(&["region", "product"], rows).unwrap();
+
+        assert_eq!(data.granularity.shape(), vec![2, 3]);
+        let values = data.values().values();
+        assert_eq!(values[..4], [1.0, 2.0, 3.0, 4.0]);
+        assert!(values[4].is_nan());
+        assert_eq!(values[5], 6.0);
+    }
+
+    #[test]
+    fn test_from_rows_duplicate_cell() {
+        let rows = vec![(vec!["EU"], 1.0), (vec!["EU"], 2.0)];
+        let result = Data::from_rows(&["region"], rows);
+        assert!(matches!(result, Err(Error::DuplicateCell(c)) if c == "{region: EU}"));
+    }
+
+    #[test]
+    fn test_from_rows_mixed_kinds() {
+        let rows = vec![
+            (vec![DimensionValue::from("EU")], 1.0),
+            (vec![DimensionValue::from(2024)], 2.0),
+        ];
+        let result = Data::from_rows(&["region"], rows);
+        assert!(matches!(result, Err(Error::InvalidGranularity(_))));
+    }
+
+    #[test]
+    fn test_typed_dimension_values() {
+        use crate::Period;
+
+        let data = Data::new_from_iter(
+            "time".to_string(),
+            [
+                (Period::monthly(2024, 1), 1.0),
+                (Period::monthly(2024, 2), 2.0),
+            ]
+            .into_iter(),
+        );
+        let query = Query {
+            dimension_name: "time".to_string(),
+            dimension_value: Period::monthly(2024, 2).into(),
+        };
+        assert_eq!(data.query(&query).values().value(0), 2.0);
+    }
 }
@@ -0,0 +1,325 @@
+//! Contains the pivot table rendering used to display `Data`.
+//!
+//! By default the highest cardinality dimension is spread across the
+//! columns and the remaining dimensions are laid out down the rows.
+
+use std::fmt;
+
+use crate::{
+    Data,
+    error::{Error, Result},
+};
+
+/// Controls how `Data` is rendered as a pivot table.
+#[derive(Debug, Clone)]
+pub struct PivotOptions {
+    /// The dimension to spread across the columns.
+    column_dimension: Option<String>,
+
+    /// The number of decimal places to show.
+    precision: Option<usize>,
+
+    /// The maximum number of rows to show before truncating.
+    max_rows: usize,
+
+    /// The maximum number of columns to show before truncating.
+    max_columns: usize,
+}
+
+impl Default for PivotOptions {
+    fn default() -> Self {
+        Self {
+            column_dimension: None,
+            precision: None,
+            max_rows: 20,
+            max_columns: 10,
+        }
+    }
+}
+
+impl PivotOptions {
+    /// Builder type API for choosing the dimension spread across the columns.
+    pub fn column_dimension(mut self, dimension_name: &str) -> Self {
+        self.column_dimension = Some(dimension_name.to_string());
+        self
+    }
+
+    /// Builder type API for setting the number of decimal places shown.
+    pub fn precision(mut self, precision: usize) -> Self {
+        self.precision = Some(precision);
+        self
+    }
+
+    /// Builder type API for setting the maximum number of rows shown.
+    pub fn max_rows(mut self, max_rows: usize) -> Self {
+        self.max_rows = max_rows;
+        self
+    }
+
+    /// Builder type API for setting the maximum number of columns shown.
+    pub fn max_columns(mut self, max_columns: usize) -> Self {
+        self.max_columns = max_columns;
+        self
+    }
+
+    fn format_value(&self, value: f64) -> String {
+        match self.precision {
+            Some(precision) => format!("{:.*}", precision, value),
+            None => value.to_string(),
+        }
+    }
+}
+
+/// A pivot table view of a piece of `Data`, see `Data::pivot`.
+pub struct Pivot<'a> {
+    data: &'a Data,
+    options: PivotOptions,
+}
+
+impl Data {
+    /// Returns a view of the data that displays as a pivot table.
+    ///
+    /// Returns an error if the data does not vary by the column dimension of
+    /// `options`.
+    pub fn pivot(&self, options: PivotOptions) -> Result<Pivot<'_>> {
+        if let Some(name) = &options.column_dimension {
+            if self.granularity.dimension_index(name).is_none() {
+                return Err(Error::UnknownDimension(name.clone()));
+            }
+            if !self.granularity.varies_by(name) {
+                return Err(Error::InvalidGranularity(format!(
+                    "cannot spread '{}' across the columns as the data does not vary by it",
+                    name
+                )));
+            }
+        }
+        Ok(Pivot {
+            data: self,
+            options,
+        })
+    }
+}
+
+impl fmt::Display for Data {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The default options have no column dimension so cannot fail.
+        self.pivot(PivotOptions::default())
+            .map_err(|_| fmt::Error)?
+            .fmt(f)
+    }
+}
+
+impl fmt::Debug for Data {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Data")
+            .field("granularity", &self.granularity)
+            .field("values", &&self.values().values()[..])
+            .field("additivity", &self.additivity)
+            .field("unit", &self.unit.as_ref().map(|u| u.to_string()))
+            .field("overrides", &self.base_and_overrides().1)
+            .field("valid_combinations", &self.valid_combinations)
+            .finish()
+    }
+}
+
+impl fmt::Display for Pivot<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let granularity = self.data.granularity();
+        let values = self.data.values().values();
+
+        // (name, labels, run-length) of each dimension the data varies by.
+        let dimensions: Vec<_> = granularity
+            .dimensions()
+            .map(|(name, values)| {
+                let labels: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+                (name, labels, *granularity.run_length(name))
+            })
+            .collect();
+
+        if dimensions.is_empty() {
+            return match values.first() {
+                Some(value) => writeln!(f, "{}", self.options.format_value(*value)),
+                None => Ok(()),
+            };
+        }
+
+        let column_idx = match &self.options.column_dimension {
+            // Checked by `Data::pivot`.
+            Some(name) => dimensions
+                .iter()
+                .position(|(n, _, _)| n == name)
+                .ok_or(fmt::Error)?,
+            None => dimensions
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, (_, labels, _))| labels.len())
+                .map(|(idx, _)| idx)
+                .unwrap(),
+        };
+        let (column_name, column_labels, column_run_length) = &dimensions[column_idx];
+        let rows: Vec<_> = dimensions
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| *idx != column_idx)
+            .map(|(_, dimension)| dimension)
+            .collect();
+
+        let shown_columns = column_labels.len().min(self.options.max_columns);
+        let total_rows: usize = rows.iter().map(|(_, labels, _)| labels.len()).product();
+        let shown_rows = total_rows.min(self.options.max_rows);
+
+        // Build the table as strings so the column widths can be computed.
+        let mut header: Vec<String> = rows.iter().map(|(name, _, _)| name.to_string()).collect();
+        if header.is_empty() {
+            header.push(String::new());
+        }
+        let label_columns = header.len();
+        header.extend(column_labels[..shown_columns].iter().cloned());
+        if shown_columns < column_labels.len() {
+            header.push("…".to_string());
+        }
+
+        let mut table = vec![header];
+        for row in 0..shown_rows {
+            // Decompose the row number into an index for each row dimension.
+            let mut remainder = row;
+            let mut offset = 0;
+            let mut line = vec![String::new(); label_columns];
+            for (idx, (_, labels, run_length)) in rows.iter().enumerate().rev() {
+                let i = remainder % labels.len();
+                remainder /= labels.len();
+                offset += i * run_length;
+                line[idx] = labels[i].clone();
+            }
+            for column in 0..shown_columns {
+                let value = values[offset + column * column_run_length];
+                line.push(self.options.format_value(value));
+            }
+            if shown_columns < column_labels.len() {
+                line.push("…".to_string());
+            }
+            table.push(line);
+        }
+
+        let widths: Vec<usize> = (0..table[0].len())
+            .map(|col| {
+                table
+                    .iter()
+                    .map(|line| line[col].chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        for (line_idx, line) in table.iter().enumerate() {
+            let mut cells = Vec::with_capacity(line.len());
+            for (col, cell) in line.iter().enumerate() {
+                let width = widths[col];
+                if col < label_columns || line_idx == 0 {
+                    cells.push(format!("{:<width$}", cell));
+                } else {
+                    cells.push(format!("{:>width$}", cell));
+                }
+            }
+            writeln!(f, "{}", cells.join(" | ").trim_end())?;
+            if line_idx == 0 {
+                let rule: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+                writeln!(f, "{}", rule.join("-+-"))?;
+            }
+        }
+        if shown_rows < total_rows {
+            let hidden = total_rows - shown_rows;
+            let noun = if hidden == 1 { "row" } else { "rows" };
+            writeln!(f, "… {} more {}", hidden, noun)?;
+        }
+        writeln!(f, "({} by {})", rows_description(&rows), column_name)
+    }
+}
+
+fn rows_description(rows: &[&(&str, Vec<String>, usize)]) -> String {
+    if rows.is_empty() {
+        "-".to_string()
+    } else {
+        rows.iter()
+            .map(|(name, _, _)| *name)
+            .collect::<Vec<_>>()
+            .join(" × ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_pivot() {
+        let headcount = Data::from_rows(
+            &["site", "team"],
+            [
+                (vec!["Lyon", "Ops"], 2.0),
+                (vec!["Lyon", "Sales"], 4.0),
+                (vec!["Lyon", "Tech"], 6.0),
+                (vec!["Oslo", "Ops"], 1.0),
+                (vec!["Oslo", "Sales"], 3.0),
+                (vec!["Oslo", "Tech"], 12.0),
+            ],
+        )
+        .unwrap();
+        let expected = "\
+site | Ops | Sales | Tech
+-----+-----+-------+-----
+Lyon |   2 |     4 |    6
+Oslo |   1 |     3 |   12
+(site by team)
+";
+        assert_eq!(headcount.to_string(), expected);
+    }
+
+    #[test]
+    fn test_pivot_options() {
+        let stock = Data::from_rows(
+            &["warehouse", "sku"],
+            [
+                (vec!["Leeds", "S1"], 1.5),
+                (vec!["Leeds", "S2"], 2.0),
+                (vec!["Leeds", "S3"], 4.0),
+                (vec!["York", "S1"], 10.5),
+                (vec!["York", "S2"], 0.0),
+                (vec!["York", "S3"], 3.0),
+            ],
+        )
+        .unwrap();
+        let options = PivotOptions::default()
+            .column_dimension("warehouse")
+            .precision(1)
+            .max_rows(2);
+        let expected = "\
+sku | Leeds | York
+----+-------+-----
+S1  |   1.5 | 10.5
+S2  |   2.0 |  0.0
+… 1 more row
+(sku by warehouse)
+";
+        assert_eq!(stock.pivot(options).unwrap().to_string(), expected);
+    }
+
+    #[test]
+    fn test_invalid_column_dimension() {
+        let data = Data::new("region".to_string(), vec!["EU", "US"], vec![3.0, 4.0]);
+        let options = PivotOptions::default().column_dimension("nope");
+        assert!(matches!(
+            data.pivot(options),
+            Err(Error::UnknownDimension(_))
+        ));
+    }
+
+    #[test]
+    fn test_debug() {
+        let data = Data::new("region".to_string(), vec!["EU", "US"], vec![1.0, 2.0]);
+        let debug = format!("{:?}", data);
+        assert!(debug.contains(r#"name: "region""#));
+        assert!(debug.contains("run_length: 1"));
+        assert!(debug.contains("values: [1.0, 2.0]"));
+    }
+}
@@ -0,0 +1,192 @@
+use std::fmt;
+
+use crate::{
+    aggregation::{Additivity, Aggregation},
+    granularity::ValueKind,
+};
+
+/// The errors that can occur when working with `Data`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A dimension with the given name does not exist.
+    UnknownDimension(String),
+
+    /// A dimension is missing from, or not varied by, a target granularity.
+    MissingDimension(String),
+
+    /// The same dimension has different values in two pieces of data.
+    ConflictingDimensionValues(String),
+
+    /// The meta-data describing the layout of the values is inconsistent.
+    InvalidGranularity(String),
+
+    /// The number of values does not match the granularity.
+    LengthMismatch { expected: usize, actual: usize },
+
+    /// A string could not be parsed as a dimension value.
+    InvalidDimensionValue { value: String, kind: ValueKind },
+
+    /// More than one value was provided for the same cell.
+    DuplicateCell(String),
+
+    /// Reading or writing data failed.
+    Io(String),
+
+    /// A dimension cannot be used as a time dimension.
+    InvalidTimeDimension { dimension: String, reason: String },
+
+    /// A formula refers to a variable that is neither an input nor a formula.
+    UnknownVariable(String),
+
+    /// A formula depends on itself, the path of the cycle is given.
+    CircularReference(Vec<String>),
+
+    /// The result of an operation would have more cells than allowed.
+    TooManyCells { cells: usize, limit: usize },
+
+    /// An operand may not be broadcast across a dimension.
+    BroadcastNotAllowed { dimension: String, reason: String },
+
+    /// An aggregation does not make sense across a dimension.
+    InvalidAggregation {
+        dimension: String,
+        aggregation: Aggregation,
+        additivity: Additivity,
+    },
+
+    /// An error occurred within the named formula.
+    Formula { name: String, error: Box<Error> },
+
+    /// A formula could not be parsed, the position is one based.
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+
+    /// A string could not be parsed as a unit.
+    InvalidUnit(String),
+
+    /// Values in different units were added, subtracted or compared.
+    UnitMismatch { lhs: String, rhs: String },
+
+    /// No conversion factor is registered between two units.
+    UnknownConversion { from: String, to: String },
+
+    /// Data without a unit cannot be converted to `to`.
+    MissingUnit { to: String },
+
+    /// A key used by `Data::lookup` is not a value of the dimension.
+    InvalidKey { dimension: String, key: String },
+
+    /// There is no rate to convert the listed cells between currencies.
+    MissingRates(Vec<String>),
+
+    /// The operands differ in a scenario dimension that only some of them
+    /// have a `base` scenario for, so they cannot be aligned.
+    UnalignedScenarios { dimension: String },
+
+    /// A field of a CSV file could not be read.
+    Csv {
+        line: u64,
+        column: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnknownDimension(name) => write!(f, "Un-recognised dimension: '{}'", name),
+            Error::MissingDimension(name) => {
+                write!(
+                    f,
+                    "Dimension '{}' is not varied by the target granularity",
+                    name
+                )
+            }
+            Error::ConflictingDimensionValues(name) => {
+                write!(f, "Dimension '{}' has conflicting values.", name)
+            }
+            Error::InvalidGranularity(reason) => write!(f, "Invalid granularity: {}", reason),
+            Error::LengthMismatch { expected, actual } => {
+                write!(f, "Expected {} values but found {}", expected, actual)
+            }
+            Error::InvalidDimensionValue { value, kind } => {
+                write!(f, "'{}' is not a valid {:?} value", value, kind)
+            }
+            Error::DuplicateCell(coordinate) => {
+                write!(f, "More than one value for the cell {}", coordinate)
+            }
+            Error::Io(reason) => write!(f, "I/O error: {}", reason),
+            Error::InvalidTimeDimension { dimension, reason } => {
+                write!(
+                    f,
+                    "'{}' is not a valid time dimension: {}",
+                    dimension, reason
+                )
+            }
+            Error::UnknownVariable(name) => write!(f, "Un-recognised variable: '{}'", name),
+            Error::CircularReference(cycle) => {
+                write!(f, "Circular reference: {}", cycle.join(" -> "))
+            }
+            Error::TooManyCells { cells, limit } => {
+                write!(
+                    f,
+                    "The result would have {} cells, the limit is {}",
+                    cells, limit
+                )
+            }
+            Error::BroadcastNotAllowed { dimension, reason } => {
+                write!(
+                    f,
+                    "Broadcasting across '{}' is not allowed: {}",
+                    dimension, reason
+                )
+            }
+            Error::InvalidAggregation {
+                dimension,
+                aggregation,
+                additivity,
+            } => write!(
+                f,
+                "Cannot aggregate using {:?} across '{}' which is {}",
+                aggregation, dimension, additivity
+            ),
+            Error::Formula { name, error } => write!(f, "In formula '{}': {}", name, error),
+            Error::Parse {
+                line,
+                column,
+                message,
+            } => write!(f, "Parse error at {}:{}: {}", line, column, message),
+            Error::InvalidUnit(unit) => write!(f, "'{}' is not a valid unit", unit),
+            Error::UnitMismatch { lhs, rhs } => {
+                write!(f, "Units '{}' and '{}' do not match", lhs, rhs)
+            }
+            Error::UnknownConversion { from, to } => {
+                write!(f, "No conversion from '{}' to '{}'", from, to)
+            }
+            Error::MissingUnit { to } => {
+                write!(f, "Cannot convert to '{}' as the data has no unit", to)
+            }
+            Error::InvalidKey { dimension, key } => {
+                write!(f, "Key '{}' is not a value of '{}'", key, dimension)
+            }
+            Error::MissingRates(cells) => write!(f, "No rate for {}", cells.join(", ")),
+            Error::UnalignedScenarios { dimension } => write!(
+                f,
+                "Cannot align the scenarios of '{}' as not every operand has a 'base' scenario",
+                dimension
+            ),
+            Error::Csv {
+                line,
+                column,
+                reason,
+            } => write!(f, "CSV line {}, column '{}': {}", line, column, reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
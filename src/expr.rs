@@ -0,0 +1,434 @@
+//! Contains the lazy `Expr` type.
+//!
+//! Building an `Expr` does no work, it only records the operations.
+//! Evaluating it resolves the output `Granularity` from the leaves first
+//! and then computes every output cell in a single pass, reading each leaf
+//! directly via its run-lengths so no intermediate arrays are allocated.
+
+use std::{borrow::Cow, collections::BTreeMap, ops};
+
+use crate::{
+    Data, DataView, Granularity,
+    aggregation::{Additivity, combine_additivity},
+    data::Values,
+    error::Result,
+    operators::{BinaryOp, BroadcastPolicy},
+    scenario::align_scenarios,
+    unit::{Unit, combine_units, combine_with_scalar},
+};
+
+/// A lazily evaluated expression over `Data`.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    /// A piece of data.
+    Data(Box<Data>),
+
+    /// A constant that is broadcast to every cell.
+    Scalar(f64),
+
+    /// The negation of an expression.
+    Neg(Box<Expr>),
+
+    /// A binary operation between two expressions.
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+
+    /// Takes the value of the second expression where the first is non-zero,
+    /// and the value of the third otherwise.
+    If(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+/// A single step of a compiled expression, which operates on a stack.
+#[derive(Debug, Clone, Copy)]
+enum Instruction {
+    /// Pushes the value of the leaf with the given index.
+    Load(usize),
+    Scalar(f64),
+    Neg,
+    Binary(BinaryOp),
+    Select,
+}
+
+impl Expr {
+    /// Creates a binary operation between `lhs` and `rhs`.
+    pub fn binary(op: BinaryOp, lhs: Expr, rhs: Expr) -> Self {
+        Expr::Binary(op, Box::new(lhs), Box::new(rhs))
+    }
+
+    /// Creates a conditional expression, see `Expr::If`.
+    pub fn if_then_else(condition: Expr, then: Expr, otherwise: Expr) -> Self {
+        Expr::If(Box::new(condition), Box::new(then), Box::new(otherwise))
+    }
+
+    /// Resolves the granularity of the result without evaluating any values.
+    ///
+    /// Returns an error if a dimension has different values in two leaves.
+    pub fn granularity(&self) -> Result<Granularity> {
+        self.granularity_with_policy(&BroadcastPolicy::default())
+    }
+
+    /// Resolves the granularity of the result, checking every operation
+    /// against `policy`, without evaluating any values.
+    pub fn granularity_with_policy(&self, policy: &BroadcastPolicy) -> Result<Granularity> {
+        Ok(self
+            .with_aligned_scenarios()?
+            .resolve(policy)?
+            .unwrap_or_else(|| Granularity::from_dimensions(Vec::new())))
+    }
+
+    fn resolve(&self, policy: &BroadcastPolicy) -> Result<Option<Granularity>> {
+        match self {
+            Expr::Data(data) => Ok(Some(data.granularity().clone())),
+            Expr::Scalar(_) => Ok(None),
+            Expr::Neg(expr) => expr.resolve(policy),
+            Expr::Binary(_, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.resolve(policy)?, rhs.resolve(policy)?);
+                policy.check(lhs.as_ref(), rhs.as_ref())
+            }
+            Expr::If(condition, then, otherwise) => {
+                // The condition is the left hand operand, see `BroadcastPolicy`.
+                let (condition, then) = (condition.resolve(policy)?, then.resolve(policy)?);
+                let lhs = policy.check(condition.as_ref(), then.as_ref())?;
+                policy.check(lhs.as_ref(), otherwise.resolve(policy)?.as_ref())
+            }
+        }
+    }
+
+    /// Resolves the unit of the result without evaluating any values, where
+    /// `None` is an unknown unit.
+    ///
+    /// Returns an error if values in different units are added, subtracted
+    /// or compared, or are the alternatives of a conditional.
+    pub fn unit(&self) -> Result<Option<Unit>> {
+        match self {
+            Expr::Data(data) => Ok(data.unit().cloned()),
+            Expr::Scalar(_) => Ok(None),
+            Expr::Neg(expr) => expr.unit(),
+            Expr::Binary(op, lhs, rhs) => combine_operand_units(*op, lhs, rhs),
+            Expr::If(condition, then, otherwise) => {
+                condition.unit()?;
+                combine_operand_units(BinaryOp::Add, then, otherwise)
+            }
+        }
+    }
+
+    /// Returns the additivity that every leaf agrees on, see
+    /// `combine_additivity`, or `None` if there are no leaves.
+    fn additivity(&self) -> Option<BTreeMap<String, Additivity>> {
+        let combine = |lhs: &Expr, rhs: &Expr| match (lhs.additivity(), rhs.additivity()) {
+            (Some(lhs), Some(rhs)) => Some(combine_additivity(&lhs, &rhs)),
+            (lhs, rhs) => lhs.or(rhs),
+        };
+        match self {
+            Expr::Data(data) => Some(data.additivity.clone()),
+            Expr::Scalar(_) => None,
+            Expr::Neg(expr) => expr.additivity(),
+            Expr::Binary(_, lhs, rhs) => combine(lhs, rhs),
+            Expr::If(_, then, otherwise) => combine(then, otherwise),
+        }
+    }
+
+    /// Returns whether the expression is a constant, with no `Data` leaves.
+    fn is_scalar(&self) -> bool {
+        match self {
+            Expr::Data(_) => false,
+            Expr::Scalar(_) => true,
+            Expr::Neg(expr) => expr.is_scalar(),
+            Expr::Binary(_, lhs, rhs) => lhs.is_scalar() && rhs.is_scalar(),
+            Expr::If(condition, then, otherwise) => {
+                condition.is_scalar() && then.is_scalar() && otherwise.is_scalar()
+            }
+        }
+    }
+
+    /// Evaluates the expression in a single pass over the output cells.
+    pub fn evaluate(&self) -> Result<Data> {
+        self.evaluate_with_policy(&BroadcastPolicy::default())
+    }
+
+    /// Evaluates the expression in a single pass over the output cells,
+    /// returning an error if an operation violates `policy`.
+    pub fn evaluate_with_policy(&self, policy: &BroadcastPolicy) -> Result<Data> {
+        let expr = self.with_aligned_scenarios()?;
+        let granularity = expr.granularity_with_policy(policy)?;
+        let unit = expr.unit()?;
+
+        let mut program = Vec::new();
+        let mut leaves = Vec::new();
+        let depth = expr.compile(&mut program, &mut leaves);
+        let leaves: Vec<_> = leaves
+            .iter()
+            .map(|data| {
+                let run_lengths = data.granularity().run_lengths_for(&granularity);
+                (data.values().values(), run_lengths)
+            })
+            .collect();
+
+        let mut stack: Vec<f64> = Vec::with_capacity(depth);
+        let mut values = Vec::with_capacity(granularity.len());
+        granularity.for_each_cell(|index| {
+            for instruction in &program {
+                let value = match *instruction {
+                    Instruction::Load(leaf) => {
+                        let (values, run_lengths) = &leaves[leaf];
+                        let offset: usize = index.iter().zip(run_lengths).map(|(i, r)| i * r).sum();
+                        values[offset]
+                    }
+                    Instruction::Scalar(value) => value,
+                    Instruction::Neg => -stack.pop().unwrap(),
+                    Instruction::Binary(op) => {
+                        let rhs = stack.pop().unwrap();
+                        let lhs = stack.pop().unwrap();
+                        op.apply(lhs, rhs)
+                    }
+                    Instruction::Select => {
+                        let otherwise = stack.pop().unwrap();
+                        let then = stack.pop().unwrap();
+                        if stack.pop().unwrap() != 0.0 {
+                            then
+                        } else {
+                            otherwise
+                        }
+                    }
+                };
+                stack.push(value);
+            }
+            values.push(stack.pop().unwrap());
+        });
+
+        let mut data = Data::from_parts(granularity, Values::from(values));
+        data.unit = unit;
+        data.additivity = expr.additivity().unwrap_or_default();
+        Ok(data)
+    }
+
+    /// Returns the expression with the scenarios of every leaf aligned, see
+    /// `Data::with_override`.
+    fn with_aligned_scenarios(&self) -> Result<Cow<'_, Expr>> {
+        let mut leaves = Vec::new();
+        self.compile(&mut Vec::new(), &mut leaves);
+        let aligned = align_scenarios(&leaves, None)?;
+        if aligned.iter().all(|leaf| matches!(leaf, Cow::Borrowed(_))) {
+            return Ok(Cow::Borrowed(self));
+        }
+        Ok(Cow::Owned(self.replace_leaves(
+            &mut aligned.into_iter().map(Cow::into_owned),
+        )))
+    }
+
+    /// Rebuilds the expression taking each leaf, in order, from `leaves`.
+    fn replace_leaves(&self, leaves: &mut impl Iterator<Item = Data>) -> Expr {
+        match self {
+            Expr::Data(_) => Expr::from(leaves.next().unwrap()),
+            Expr::Scalar(value) => Expr::Scalar(*value),
+            Expr::Neg(expr) => -expr.replace_leaves(leaves),
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.replace_leaves(leaves);
+                Expr::binary(*op, lhs, rhs.replace_leaves(leaves))
+            }
+            Expr::If(condition, then, otherwise) => {
+                let condition = condition.replace_leaves(leaves);
+                let then = then.replace_leaves(leaves);
+                Expr::if_then_else(condition, then, otherwise.replace_leaves(leaves))
+            }
+        }
+    }
+
+    /// Appends the instructions for the expression to `program` in postfix
+    /// order, returning the maximum stack depth required.
+    fn compile<'a>(&'a self, program: &mut Vec<Instruction>, leaves: &mut Vec<&'a Data>) -> usize {
+        match self {
+            Expr::Data(data) => {
+                program.push(Instruction::Load(leaves.len()));
+                leaves.push(&**data);
+                1
+            }
+            Expr::Scalar(value) => {
+                program.push(Instruction::Scalar(*value));
+                1
+            }
+            Expr::Neg(expr) => {
+                let depth = expr.compile(program, leaves);
+                program.push(Instruction::Neg);
+                depth
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.compile(program, leaves);
+                let rhs = rhs.compile(program, leaves);
+                program.push(Instruction::Binary(*op));
+                lhs.max(rhs + 1)
+            }
+            Expr::If(condition, then, otherwise) => {
+                let condition = condition.compile(program, leaves);
+                let then = then.compile(program, leaves);
+                let otherwise = otherwise.compile(program, leaves);
+                program.push(Instruction::Select);
+                condition.max(then + 1).max(otherwise + 2)
+            }
+        }
+    }
+}
+
+/// Combines the units of the operands of `op`, where a constant operand
+/// takes on the unit of the other, see `combine_with_scalar`.
+fn combine_operand_units(op: BinaryOp, lhs: &Expr, rhs: &Expr) -> Result<Option<Unit>> {
+    match (lhs.is_scalar(), rhs.is_scalar()) {
+        (true, true) => Ok(None),
+        (true, false) => Ok(combine_with_scalar(op, rhs.unit()?.as_ref(), true)),
+        (false, true) => Ok(combine_with_scalar(op, lhs.unit()?.as_ref(), false)),
+        (false, false) => combine_units(op, lhs.unit()?.as_ref(), rhs.unit()?.as_ref()),
+    }
+}
+
+impl From<Data> for Expr {
+    fn from(data: Data) -> Self {
+        Expr::Data(Box::new(data))
+    }
+}
+
+impl From<&Data> for Expr {
+    fn from(data: &Data) -> Self {
+        Expr::Data(Box::new(data.clone()))
+    }
+}
+
+impl From<&DataView<'_>> for Expr {
+    fn from(view: &DataView<'_>) -> Self {
+        Expr::Data(Box::new(view.to_data()))
+    }
+}
+
+impl From<f64> for Expr {
+    fn from(value: f64) -> Self {
+        Expr::Scalar(value)
+    }
+}
+
+macro_rules! impl_binary_op {
+    ($trait:ident, $method:ident, $op:expr) => {
+        impl<T: Into<Expr>> ops::$trait<T> for Expr {
+            type Output = Expr;
+
+            fn $method(self, rhs: T) -> Expr {
+                Expr::binary($op, self, rhs.into())
+            }
+        }
+    };
+}
+
+impl_binary_op!(Add, add, BinaryOp::Add);
+impl_binary_op!(Sub, sub, BinaryOp::Sub);
+impl_binary_op!(Mul, mul, BinaryOp::Mul);
+impl_binary_op!(Div, div, BinaryOp::Div);
+
+impl ops::Neg for Expr {
+    type Output = Expr;
+
+    fn neg(self) -> Expr {
+        Expr::Neg(Box::new(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, operators::mul};
+
+    fn product(values: Vec<f64>) -> Data {
+        Data::new("product".to_string(), vec!["A", "B", "C"], values)
+    }
+
+    fn region(values: Vec<f64>) -> Data {
+        Data::new("region".to_string(), vec!["EU", "US"], values)
+    }
+
+    #[test]
+    fn test_fused_evaluation() {
+        let a = product(vec![1.0, 2.0, 3.0]);
+        let b = region(vec![10.0, 100.0]);
+        let c = product(vec![1.0, 1.0, 1.0]);
+        let d = product(vec![4.0, 5.0, 6.0]);
+        let e = region(vec![1.0, 2.0]);
+
+        let expr = Expr::from(&a) * &b + Expr::from(&c) * &d - &e;
+        let result = expr.evaluate().unwrap();
+
+        assert!(result.granularity() == mul(&a, &b).granularity());
+        assert_eq!(
+            result.values().values().to_vec(),
+            vec![13.0, 24.0, 35.0, 102.0, 203.0, 304.0]
+        );
+    }
+
+    #[test]
+    fn test_units() {
+        let price = product(vec![1.0, 2.0, 3.0]).with_unit(Unit::parse("USD/unit").unwrap());
+        let volume = region(vec![10.0, 100.0]).with_unit(Unit::base("unit"));
+
+        let revenue = (Expr::from(&price) * &volume * 2.0).evaluate().unwrap();
+        assert_eq!(revenue.unit(), Some(&Unit::base("USD")));
+
+        let expr = Expr::if_then_else(
+            Expr::binary(BinaryOp::Gt, Expr::from(&volume), Expr::Scalar(0.0)),
+            Expr::from(&price),
+            Expr::from(&volume),
+        );
+        assert_eq!(
+            expr.evaluate().unwrap_err(),
+            Error::UnitMismatch {
+                lhs: "USD/unit".to_string(),
+                rhs: "unit".to_string()
+            }
+        );
+
+        // Constants take on the unit of the data, unitless data stays unknown.
+        let per_unit = (Expr::Scalar(1.0) / &volume).unit().unwrap();
+        assert_eq!(per_unit, Some(Unit::parse("1/unit").unwrap()));
+        let shifted = (Expr::from(&volume) + 1.0).unit().unwrap();
+        assert_eq!(shifted, Some(Unit::base("unit")));
+        let unknown = (Expr::from(&price) + &region(vec![1.0, 2.0]))
+            .unit()
+            .unwrap();
+        assert_eq!(unknown, None);
+    }
+
+    #[test]
+    fn test_scalars() {
+        let a = product(vec![1.0, 2.0, 3.0]);
+        let result = (-(Expr::from(&a) / 2.0) + 1.0).evaluate().unwrap();
+        assert_eq!(result.values().values().to_vec(), vec![0.5, 0.0, -0.5]);
+
+        let scalar = (Expr::from(2.0) * 3.0).evaluate().unwrap();
+        assert_eq!(scalar.values().values().to_vec(), vec![6.0]);
+    }
+
+    #[test]
+    fn test_policy() {
+        let a = product(vec![1.0, 2.0, 3.0]);
+        let b = region(vec![10.0, 100.0]);
+        let expr = Expr::from(&a) * 2.0 + &b;
+
+        let policy = BroadcastPolicy::default().max_cells(5);
+        assert!(matches!(
+            expr.evaluate_with_policy(&policy),
+            Err(Error::TooManyCells { cells: 6, limit: 5 })
+        ));
+        let policy = BroadcastPolicy::default()
+            .tagged_only()
+            .broadcastable("region");
+        assert!(matches!(
+            expr.evaluate_with_policy(&policy),
+            Err(Error::BroadcastNotAllowed { dimension, .. }) if dimension == "product"
+        ));
+    }
+
+    #[test]
+    fn test_conflicting_dimension_values() {
+        let a = product(vec![1.0, 2.0, 3.0]);
+        let b = Data::new("product".to_string(), vec!["A", "B"], vec![1.0, 2.0]);
+        let expr = Expr::from(&a) + &b;
+        assert!(matches!(
+            expr.granularity(),
+            Err(Error::ConflictingDimensionValues(name)) if name == "product"
+        ));
+    }
+}
@@ -0,0 +1,190 @@
+//! Contains the static inference of the granularity of a formula.
+//!
+//! Inference only looks at the `Granularity` of each variable so it can be
+//! used to check a model before any values are calculated.  Alongside the
+//! resulting granularity it reports every broadcast that evaluating the
+//! formula would perform, which is where models tend to blow up.
+
+use std::fmt;
+
+use crate::{Granularity, error::Result, operators::BroadcastPolicy};
+
+use super::Formula;
+
+/// A broadcast that an operation within a formula would perform.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Broadcast {
+    /// The operation that broadcasts, e.g. `revenue + cost`.
+    pub operation: String,
+
+    /// The operand that is broadcast, e.g. `revenue`.
+    pub operand: String,
+
+    /// The name and number of values of each dimension the operand is
+    /// broadcast across.
+    pub dimensions: Vec<(String, usize)>,
+}
+
+impl Broadcast {
+    /// Returns the number of times each value of the operand is repeated.
+    pub fn factor(&self) -> usize {
+        self.dimensions.iter().map(|(_, size)| size).product()
+    }
+}
+
+impl fmt::Display for Broadcast {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dimensions: Vec<String> = self
+            .dimensions
+            .iter()
+            .map(|(name, size)| format!("{} values of '{}'", size, name))
+            .collect();
+        write!(
+            f,
+            "`{}` would broadcast `{}` across {}",
+            self.operation,
+            self.operand,
+            dimensions.join(" × ")
+        )
+    }
+}
+
+/// The result of inferring the granularity of a formula.
+#[derive(Debug, Clone)]
+pub struct Inference {
+    /// The granularity of the result.
+    pub granularity: Granularity,
+
+    /// Every broadcast that evaluating the formula would perform.
+    pub broadcasts: Vec<Broadcast>,
+}
+
+impl Formula {
+    /// Infers the granularity of the result of the formula, using `lookup` to
+    /// find the granularity of each variable, without evaluating any values.
+    ///
+    /// Returns an error if a dimension has different values in two operands.
+    pub fn infer<'a>(
+        &self,
+        lookup: &impl Fn(&str) -> Result<&'a Granularity>,
+    ) -> Result<Inference> {
+        self.infer_with_policy(lookup, &BroadcastPolicy::default())
+    }
+
+    /// Infers the granularity of the result of the formula, see `infer`,
+    /// returning an error if an operation violates `policy`.
+    pub fn infer_with_policy<'a>(
+        &self,
+        lookup: &impl Fn(&str) -> Result<&'a Granularity>,
+        policy: &BroadcastPolicy,
+    ) -> Result<Inference> {
+        let mut broadcasts = Vec::new();
+        let granularity = self
+            .infer_operand(lookup, policy, &mut broadcasts)?
+            .unwrap_or_else(|| Granularity::from_dimensions(Vec::new()));
+        Ok(Inference {
+            granularity,
+            broadcasts,
+        })
+    }
+
+    /// Infers the granularity of the formula, which is `None` for scalars.
+    fn infer_operand<'a>(
+        &self,
+        lookup: &impl Fn(&str) -> Result<&'a Granularity>,
+        policy: &BroadcastPolicy,
+        broadcasts: &mut Vec<Broadcast>,
+    ) -> Result<Option<Granularity>> {
+        let operands: Vec<&Formula> = match self {
+            Formula::Variable(name) => return Ok(Some(lookup(name)?.clone())),
+            Formula::Scalar(_) => return Ok(None),
+            Formula::Neg(formula) => return formula.infer_operand(lookup, policy, broadcasts),
+            Formula::Binary(_, lhs, rhs) => vec![lhs, rhs],
+            Formula::If(condition, then, otherwise) => vec![condition, then, otherwise],
+        };
+
+        let mut granularities = Vec::with_capacity(operands.len());
+        for operand in &operands {
+            granularities.push(operand.infer_operand(lookup, policy, broadcasts)?);
+        }
+        let mut result: Option<Granularity> = None;
+        for granularity in &granularities {
+            result = policy.check(result.as_ref(), granularity.as_ref())?;
+        }
+
+        if let Some(result) = &result {
+            for (operand, granularity) in operands.iter().zip(&granularities) {
+                let Some(granularity) = granularity else {
+                    continue;
+                };
+                let dimensions: Vec<(String, usize)> = result
+                    .dimensions()
+                    .filter(|(name, _)| !granularity.maybe_varies_by(name))
+                    .map(|(name, values)| (name.to_string(), values.len()))
+                    .collect();
+                if !dimensions.is_empty() {
+                    broadcasts.push(Broadcast {
+                        operation: self.to_string(),
+                        operand: operand.to_string(),
+                        dimensions,
+                    });
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{Data, Error};
+
+    #[test]
+    fn test_infer_broadcast() {
+        let skus: Vec<String> = (0..50_000).map(|i| format!("SKU{}", i)).collect();
+        let revenue = Data::new("region".to_string(), vec!["EU", "US"], vec![0.0; 2]);
+        let cost = Data::new("sku".to_string(), skus, vec![0.0; 50_000]);
+        let granularities = HashMap::from([
+            ("revenue", revenue.granularity().clone()),
+            ("cost", cost.granularity().clone()),
+        ]);
+        let lookup = |name: &str| {
+            granularities
+                .get(name)
+                .ok_or_else(|| Error::UnknownVariable(name.to_string()))
+        };
+
+        let inference = Formula::parse("revenue * 2 + cost")
+            .unwrap()
+            .infer(&lookup)
+            .unwrap();
+        assert_eq!(inference.granularity.shape(), vec![2, 50_000]);
+        assert_eq!(inference.broadcasts.len(), 2);
+        assert_eq!(
+            inference.broadcasts[0].to_string(),
+            "`revenue * 2 + cost` would broadcast `revenue * 2` across 50000 values of 'sku'"
+        );
+        assert_eq!(inference.broadcasts[1].factor(), 2);
+    }
+
+    #[test]
+    fn test_infer_conflict() {
+        let revenue = Data::new("region".to_string(), vec!["EU", "US", "APAC"], vec![0.0; 3]);
+        let tax = Data::new("region".to_string(), vec!["EU"], vec![0.0]);
+        let granularities = HashMap::from([
+            ("revenue", revenue.granularity().clone()),
+            ("tax", tax.granularity().clone()),
+        ]);
+        let lookup = |name: &str| {
+            granularities
+                .get(name)
+                .ok_or_else(|| Error::UnknownVariable(name.to_string()))
+        };
+
+        let result = Formula::parse("revenue - tax").unwrap().infer(&lookup);
+        assert!(matches!(result, Err(Error::ConflictingDimensionValues(name)) if name == "region"));
+    }
+}
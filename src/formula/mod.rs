@@ -0,0 +1,197 @@
+//! Contains the `Formula` type, an expression over named variables.
+//!
+//! A formula is resolved into an `Expr` once the `Data` for each of its
+//! variables is known, see `Model`.  Formulas can also be parsed from
+//! text, see `Formula::parse`, and their granularity can be inferred
+//! without any values, see `Formula::infer`.
+
+use std::{fmt, ops};
+
+use crate::{
+    Data,
+    error::{Error, Result},
+    expr::Expr,
+    operators::BinaryOp,
+};
+
+mod inference;
+pub(crate) mod parser;
+
+pub use inference::{Broadcast, Inference};
+
+/// An expression over named variables.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Formula {
+    /// A reference to an input or another formula.
+    Variable(String),
+
+    /// A constant.
+    Scalar(f64),
+
+    /// The negation of a formula.
+    Neg(Box<Formula>),
+
+    /// A binary operation between two formulas.
+    Binary(BinaryOp, Box<Formula>, Box<Formula>),
+
+    /// Takes the value of the second formula where the first is non-zero,
+    /// and the value of the third otherwise.
+    If(Box<Formula>, Box<Formula>, Box<Formula>),
+}
+
+impl Formula {
+    /// Parses `source` as an expression, e.g. `price * volume - discount`.
+    pub fn parse(source: &str) -> Result<Self> {
+        parser::parse_expression(source)
+    }
+
+    /// Parses `source` as a definition, e.g. `revenue = price * volume`,
+    /// returning the name and the formula.
+    pub fn parse_definition(source: &str) -> Result<(String, Self)> {
+        let mut definitions = parser::parse_definitions(source)?;
+        match definitions.len() {
+            1 => Ok(definitions.remove(0)),
+            found => Err(Error::Parse {
+                line: 1,
+                column: 1,
+                message: format!("expected one definition but found {}", found),
+            }),
+        }
+    }
+
+    /// Creates a reference to the variable `name`.
+    pub fn var(name: &str) -> Self {
+        Formula::Variable(name.to_string())
+    }
+
+    /// Creates a binary operation between `lhs` and `rhs`.
+    pub fn binary(op: BinaryOp, lhs: Formula, rhs: Formula) -> Self {
+        Formula::Binary(op, Box::new(lhs), Box::new(rhs))
+    }
+
+    /// Creates a conditional formula, see `Formula::If`.
+    pub fn if_then_else(condition: Formula, then: Formula, otherwise: Formula) -> Self {
+        Formula::If(Box::new(condition), Box::new(then), Box::new(otherwise))
+    }
+
+    /// Returns the names of the variables the formula refers to, in the
+    /// order they first appear.
+    pub fn variables(&self) -> Vec<&str> {
+        let mut variables = Vec::new();
+        self.visit_variables(&mut |name| {
+            if !variables.contains(&name) {
+                variables.push(name);
+            }
+        });
+        variables
+    }
+
+    fn visit_variables<'a>(&'a self, f: &mut impl FnMut(&'a str)) {
+        match self {
+            Formula::Variable(name) => f(name),
+            Formula::Scalar(_) => {}
+            Formula::Neg(formula) => formula.visit_variables(f),
+            Formula::Binary(_, lhs, rhs) => {
+                lhs.visit_variables(f);
+                rhs.visit_variables(f);
+            }
+            Formula::If(condition, then, otherwise) => {
+                condition.visit_variables(f);
+                then.visit_variables(f);
+                otherwise.visit_variables(f);
+            }
+        }
+    }
+
+    /// Converts the formula to an `Expr`, using `lookup` to find the `Data`
+    /// for each variable.
+    pub fn to_expr<'a>(&self, lookup: &impl Fn(&str) -> Result<&'a Data>) -> Result<Expr> {
+        Ok(match self {
+            Formula::Variable(name) => Expr::from(lookup(name)?),
+            Formula::Scalar(value) => Expr::Scalar(*value),
+            Formula::Neg(formula) => -formula.to_expr(lookup)?,
+            Formula::Binary(op, lhs, rhs) => {
+                Expr::binary(*op, lhs.to_expr(lookup)?, rhs.to_expr(lookup)?)
+            }
+            Formula::If(condition, then, otherwise) => Expr::if_then_else(
+                condition.to_expr(lookup)?,
+                then.to_expr(lookup)?,
+                otherwise.to_expr(lookup)?,
+            ),
+        })
+    }
+
+    /// Writes the formula, adding brackets where an operand binds less
+    /// tightly than `precedence`.
+    fn write(&self, f: &mut fmt::Formatter<'_>, precedence: u8) -> fmt::Result {
+        match self {
+            Formula::Variable(name) => write!(f, "{}", name),
+            Formula::Scalar(value) => write!(f, "{}", value),
+            Formula::Neg(formula) => {
+                write!(f, "-")?;
+                formula.write(f, u8::MAX)
+            }
+            Formula::Binary(op, lhs, rhs) => {
+                let bracket = op.precedence() < precedence;
+                if bracket {
+                    write!(f, "(")?;
+                }
+                lhs.write(f, op.precedence())?;
+                write!(f, " {} ", op)?;
+                // Operations are left associative so the right hand side must
+                // bind more tightly.
+                rhs.write(f, op.precedence() + 1)?;
+                if bracket {
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            Formula::If(condition, then, otherwise) => {
+                write!(f, "if({}, {}, {})", condition, then, otherwise)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+impl From<f64> for Formula {
+    fn from(value: f64) -> Self {
+        Formula::Scalar(value)
+    }
+}
+
+impl From<&str> for Formula {
+    fn from(name: &str) -> Self {
+        Formula::var(name)
+    }
+}
+
+macro_rules! impl_binary_op {
+    ($trait:ident, $method:ident, $op:expr) => {
+        impl<T: Into<Formula>> ops::$trait<T> for Formula {
+            type Output = Formula;
+
+            fn $method(self, rhs: T) -> Formula {
+                Formula::binary($op, self, rhs.into())
+            }
+        }
+    };
+}
+
+impl_binary_op!(Add, add, BinaryOp::Add);
+impl_binary_op!(Sub, sub, BinaryOp::Sub);
+impl_binary_op!(Mul, mul, BinaryOp::Mul);
+impl_binary_op!(Div, div, BinaryOp::Div);
+
+impl ops::Neg for Formula {
+    type Output = Formula;
+
+    fn neg(self) -> Formula {
+        Formula::Neg(Box::new(self))
+    }
+}
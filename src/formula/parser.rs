@@ -0,0 +1,317 @@
+//! Contains the parser for the formula language.
+//!
+//! The grammar, from lowest to highest precedence, is:
+//!
+//! ```text
+//! definition := identifier "=" expression
+//! expression := sum (("<" | "<=" | ">" | ">=" | "==" | "!=") sum)?
+//! sum        := product (("+" | "-") product)*
+//! product    := unary (("*" | "/") unary)*
+//! unary      := "-" unary | primary
+//! primary    := number | identifier | identifier "(" arguments ")" | "(" expression ")"
+//! ```
+//!
+//! The only function is `if(condition, then, otherwise)`.  Text from a `#`
+//! to the end of the line is a comment.
+
+use crate::{
+    error::{Error, Result},
+    operators::BinaryOp,
+};
+
+use super::Formula;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token<'a> {
+    Number(f64),
+    Identifier(&'a str),
+    Symbol(&'static str),
+    End,
+}
+
+impl std::fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "'{}'", value),
+            Token::Identifier(name) => write!(f, "'{}'", name),
+            Token::Symbol(symbol) => write!(f, "'{}'", symbol),
+            Token::End => write!(f, "the end of the input"),
+        }
+    }
+}
+
+/// The symbols of the language, longer symbols first so they take priority.
+const SYMBOLS: [&str; 15] = [
+    "<=", ">=", "==", "!=", "<", ">", "+", "-", "*", "/", "(", ")", ",", "=", ";",
+];
+
+struct Parser<'a> {
+    source: &'a str,
+
+    /// Each token along with its byte offset into `source`.
+    tokens: Vec<(Token<'a>, usize)>,
+    next: usize,
+}
+
+/// Parses `source` as a single expression.
+pub(crate) fn parse_expression(source: &str) -> Result<Formula> {
+    let mut parser = Parser::new(source)?;
+    let formula = parser.expression()?;
+    parser.expect_end()?;
+    Ok(formula)
+}
+
+/// Parses `source` as a sequence of definitions, optionally separated by
+/// `;`.
+pub(crate) fn parse_definitions(source: &str) -> Result<Vec<(String, Formula)>> {
+    let mut parser = Parser::new(source)?;
+    let mut definitions = Vec::new();
+    while parser.peek() != Token::End {
+        definitions.push(parser.definition()?);
+        if parser.peek() == Token::Symbol(";") {
+            parser.advance();
+        }
+    }
+    Ok(definitions)
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> Result<Self> {
+        let mut tokens = Vec::new();
+        let mut offset = 0;
+        while let Some(c) = source[offset..].chars().next() {
+            let rest = &source[offset..];
+            if c.is_whitespace() {
+                offset += c.len_utf8();
+            } else if c == '#' {
+                offset += rest.find('\n').unwrap_or(rest.len());
+            } else if c.is_ascii_digit() || c == '.' {
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                    .unwrap_or(rest.len());
+                let value = rest[..len].parse().map_err(|_| {
+                    error(
+                        source,
+                        offset,
+                        format!("'{}' is not a number", &rest[..len]),
+                    )
+                })?;
+                tokens.push((Token::Number(value), offset));
+                offset += len;
+            } else if c.is_alphabetic() || c == '_' {
+                let len = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                tokens.push((Token::Identifier(&rest[..len]), offset));
+                offset += len;
+            } else if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+                tokens.push((Token::Symbol(symbol), offset));
+                offset += symbol.len();
+            } else {
+                return Err(error(source, offset, format!("unexpected '{}'", c)));
+            }
+        }
+        tokens.push((Token::End, source.len()));
+
+        Ok(Self {
+            source,
+            tokens,
+            next: 0,
+        })
+    }
+
+    fn peek(&self) -> Token<'a> {
+        self.tokens[self.next].0
+    }
+
+    fn advance(&mut self) -> Token<'a> {
+        let token = self.peek();
+        if token != Token::End {
+            self.next += 1;
+        }
+        token
+    }
+
+    /// Returns an error at the next token.
+    fn error(&self, message: String) -> Error {
+        error(self.source, self.tokens[self.next].1, message)
+    }
+
+    fn unexpected(&self, expected: &str) -> Error {
+        self.error(format!("expected {} but found {}", expected, self.peek()))
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> Result<()> {
+        if self.peek() != Token::Symbol(symbol) {
+            return Err(self.unexpected(&format!("'{}'", symbol)));
+        }
+        self.advance();
+        Ok(())
+    }
+
+    fn expect_end(&self) -> Result<()> {
+        match self.peek() {
+            Token::End => Ok(()),
+            _ => Err(self.unexpected("an operator")),
+        }
+    }
+
+    fn definition(&mut self) -> Result<(String, Formula)> {
+        let Token::Identifier(name) = self.peek() else {
+            return Err(self.unexpected("a name"));
+        };
+        self.advance();
+        self.expect("=")?;
+        Ok((name.to_string(), self.expression()?))
+    }
+
+    fn expression(&mut self) -> Result<Formula> {
+        let lhs = self.sum()?;
+        let op = match self.peek() {
+            Token::Symbol("<") => BinaryOp::Lt,
+            Token::Symbol("<=") => BinaryOp::Le,
+            Token::Symbol(">") => BinaryOp::Gt,
+            Token::Symbol(">=") => BinaryOp::Ge,
+            Token::Symbol("==") => BinaryOp::Eq,
+            Token::Symbol("!=") => BinaryOp::Ne,
+            _ => return Ok(lhs),
+        };
+        self.advance();
+        Ok(Formula::binary(op, lhs, self.sum()?))
+    }
+
+    fn sum(&mut self) -> Result<Formula> {
+        let mut lhs = self.product()?;
+        loop {
+            let op = match self.peek() {
+                Token::Symbol("+") => BinaryOp::Add,
+                Token::Symbol("-") => BinaryOp::Sub,
+                _ => return Ok(lhs),
+            };
+            self.advance();
+            lhs = Formula::binary(op, lhs, self.product()?);
+        }
+    }
+
+    fn product(&mut self) -> Result<Formula> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Token::Symbol("*") => BinaryOp::Mul,
+                Token::Symbol("/") => BinaryOp::Div,
+                _ => return Ok(lhs),
+            };
+            self.advance();
+            lhs = Formula::binary(op, lhs, self.unary()?);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Formula> {
+        if self.peek() == Token::Symbol("-") {
+            self.advance();
+            return Ok(-self.unary()?);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Formula> {
+        match self.peek() {
+            Token::Number(value) => {
+                self.advance();
+                Ok(Formula::Scalar(value))
+            }
+            Token::Identifier(name) => {
+                let function = self.next;
+                self.advance();
+                if self.peek() != Token::Symbol("(") {
+                    return Ok(Formula::var(name));
+                }
+                if name != "if" {
+                    self.next = function;
+                    return Err(self.error(format!("unknown function '{}'", name)));
+                }
+                self.advance();
+                let condition = self.expression()?;
+                self.expect(",")?;
+                let then = self.expression()?;
+                self.expect(",")?;
+                let otherwise = self.expression()?;
+                self.expect(")")?;
+                Ok(Formula::if_then_else(condition, then, otherwise))
+            }
+            Token::Symbol("(") => {
+                self.advance();
+                let formula = self.expression()?;
+                self.expect(")")?;
+                Ok(formula)
+            }
+            _ => Err(self.unexpected("a value")),
+        }
+    }
+}
+
+/// Creates a parse error at the byte `offset` into `source`.
+fn error(source: &str, offset: usize, message: String) -> Error {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|idx| idx + 1).unwrap_or(0);
+    let column = before[line_start..].chars().count() + 1;
+    Error::Parse {
+        line,
+        column,
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_precedence() {
+        let formula = parse_expression("price * volume - discount / 2").unwrap();
+        let expected = Formula::var("price") * "volume" - Formula::var("discount") / 2.0;
+        assert_eq!(formula, expected);
+
+        let formula = parse_expression("a - (b - c) * -d").unwrap();
+        assert_eq!(formula.to_string(), "a - (b - c) * -d");
+    }
+
+    #[test]
+    fn test_definitions() {
+        let source = "\
+# Derived figures
+revenue = price * volume - discount
+margin = if(revenue > 0, profit / revenue, 0)
+";
+        let definitions = parse_definitions(source).unwrap();
+        let names: Vec<_> = definitions.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["revenue", "margin"]);
+        assert_eq!(
+            definitions[1].1.to_string(),
+            "if(revenue > 0, profit / revenue, 0)"
+        );
+    }
+
+    #[test]
+    fn test_error_positions() {
+        let error = parse_definitions("a = b\nc = d * * e").unwrap_err();
+        assert_eq!(
+            error,
+            Error::Parse {
+                line: 2,
+                column: 9,
+                message: "expected a value but found '*'".to_string()
+            }
+        );
+
+        let error = parse_expression("max(a, b)").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Parse error at 1:1: unknown function 'max'"
+        );
+
+        let error = parse_expression("if(a, b c)").unwrap_err();
+        assert!(matches!(error, Error::Parse { column: 9, .. }));
+    }
+}
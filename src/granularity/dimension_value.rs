@@ -0,0 +1,160 @@
+//! Contains the implementation of the `DimensionValue` type.
+//!
+//! Dimension values are typed so that queries and joins compare
+//! values rather than their string representation.  Every value within
+//! a single dimension must be of the same `ValueKind`.
+
+use std::fmt;
+
+use chrono::NaiveDate;
+
+use crate::error::{Error, Result};
+
+use super::period::Period;
+
+/// A single value that a dimension can take.
+///
+/// Values of the same kind are ordered naturally, i.e. integers
+/// numerically and dates and periods chronologically.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DimensionValue {
+    Integer(i64),
+    Date(NaiveDate),
+    Period(Period),
+    String(String),
+}
+
+/// The kind of a `DimensionValue`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ValueKind {
+    Integer,
+    Date,
+    Period,
+    String,
+}
+
+impl DimensionValue {
+    pub fn kind(&self) -> ValueKind {
+        match self {
+            DimensionValue::Integer(_) => ValueKind::Integer,
+            DimensionValue::Date(_) => ValueKind::Date,
+            DimensionValue::Period(_) => ValueKind::Period,
+            DimensionValue::String(_) => ValueKind::String,
+        }
+    }
+
+    /// Parses `s` as a value of `kind`.
+    ///
+    /// Dates use the `YYYY-MM-DD` format and periods are parsed as calendar
+    /// periods, see `Period::parse`.
+    pub fn parse(s: &str, kind: ValueKind) -> Result<Self> {
+        let invalid = || Error::InvalidDimensionValue {
+            value: s.to_string(),
+            kind,
+        };
+        match kind {
+            ValueKind::Integer => s.trim().parse().map(Self::Integer).map_err(|_| invalid()),
+            ValueKind::Date => NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d")
+                .map(Self::Date)
+                .map_err(|_| invalid()),
+            ValueKind::Period => s.parse().map(Self::Period),
+            ValueKind::String => Ok(Self::String(s.to_string())),
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            DimensionValue::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn as_date(&self) -> Option<NaiveDate> {
+        match self {
+            DimensionValue::Date(d) => Some(*d),
+            _ => None,
+        }
+    }
+
+    pub fn as_period(&self) -> Option<Period> {
+        match self {
+            DimensionValue::Period(p) => Some(*p),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            DimensionValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DimensionValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DimensionValue::Integer(i) => write!(f, "{}", i),
+            DimensionValue::Date(d) => write!(f, "{}", d.format("%Y-%m-%d")),
+            DimensionValue::Period(p) => write!(f, "{}", p),
+            DimensionValue::String(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl From<String> for DimensionValue {
+    fn from(value: String) -> Self {
+        DimensionValue::String(value)
+    }
+}
+
+impl From<&str> for DimensionValue {
+    fn from(value: &str) -> Self {
+        DimensionValue::String(value.to_string())
+    }
+}
+
+impl From<i64> for DimensionValue {
+    fn from(value: i64) -> Self {
+        DimensionValue::Integer(value)
+    }
+}
+
+impl From<i32> for DimensionValue {
+    fn from(value: i32) -> Self {
+        DimensionValue::Integer(value.into())
+    }
+}
+
+impl From<NaiveDate> for DimensionValue {
+    fn from(value: NaiveDate) -> Self {
+        DimensionValue::Date(value)
+    }
+}
+
+impl From<Period> for DimensionValue {
+    fn from(value: Period) -> Self {
+        DimensionValue::Period(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integers_sort_numerically() {
+        let mut values: Vec<DimensionValue> = vec![10.into(), 9.into(), 100.into()];
+        values.sort();
+        assert_eq!(values, vec![9.into(), 10.into(), 100.into()]);
+    }
+
+    #[test]
+    fn test_typed_values_are_not_strings() {
+        let period: DimensionValue = Period::monthly(2024, 3).into();
+        assert_eq!(period.to_string(), "2024-03");
+        assert_ne!(period, DimensionValue::from("2024-03"));
+    }
+}
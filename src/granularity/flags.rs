@@ -5,7 +5,12 @@ use bitvec::vec::BitVec;
 ///
 /// The available dimensions themselves are tracked by
 /// `PossibleDimensions`.
-#[derive(PartialEq, Eq, Clone)]
+#[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "FlagsRepr", into = "FlagsRepr")
+)]
 pub struct Flags {
     /// Indicates the dimensions that are in use.
     flags: BitVec,
@@ -15,6 +20,13 @@ pub struct Flags {
 }
 
 impl Flags {
+    /// Creates a new `Flags` where `flags` indicates the dimensions in use and
+    /// `sizes` the size of each dimension.
+    pub fn new(flags: BitVec, sizes: &[usize]) -> Self {
+        let run_lengths = compute_run_lengths(&flags, sizes);
+        Self { flags, run_lengths }
+    }
+
     /// Indicates the number of dimensions.
     pub fn size(&self) -> usize {
         self.run_lengths.len()
@@ -32,9 +44,17 @@ impl Flags {
         &self.run_lengths[idx]
     }
 
-    pub fn drop(&mut self, idx: usize) {
+    pub fn drop(&mut self, idx: usize, sizes: &[usize]) {
         self.flags.set(idx, false);
-        self.run_lengths[idx] = 0;
+        self.run_lengths = compute_run_lengths(&self.flags, sizes);
+    }
+
+    /// Indicates if the flags and run-lengths are consistent with dimensions
+    /// of `sizes`.
+    pub fn is_consistent(&self, sizes: &[usize]) -> bool {
+        self.flags.len() == sizes.len()
+            && self.run_lengths.len() == sizes.len()
+            && self.run_lengths == compute_run_lengths(&self.flags, sizes)
     }
 
     pub fn broadcast(&self, other: &Self, sizes: &[usize]) -> Self {
@@ -55,6 +75,34 @@ impl Default for Flags {
     }
 }
 
+/// The serialized form of `Flags`, which stores the flags as plain booleans.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct FlagsRepr {
+    flags: Vec<bool>,
+    run_lengths: Vec<usize>,
+}
+
+#[cfg(feature = "serde")]
+impl From<FlagsRepr> for Flags {
+    fn from(repr: FlagsRepr) -> Self {
+        Self {
+            flags: repr.flags.into_iter().collect(),
+            run_lengths: repr.run_lengths,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<Flags> for FlagsRepr {
+    fn from(flags: Flags) -> Self {
+        Self {
+            flags: flags.flags.iter().by_vals().collect(),
+            run_lengths: flags.run_lengths,
+        }
+    }
+}
+
 /// Returns a run-length `Vec` indicating the run-lengths for each dimension represented in
 /// `flags` / `sizes`.
 ///
@@ -66,23 +114,15 @@ impl Default for Flags {
 ///
 /// The run-length for a specific slot will be `0` if the corresponding slot in `flags` is `false`.
 fn compute_run_lengths(flags: &BitVec, sizes: &[usize]) -> Vec<usize> {
-    let dims = sizes
-        .iter()
-        .zip(flags.iter())
-        .map(|(size, flag)| if *flag { *size } else { 1 })
-        .collect::<Vec<_>>();
-
-    let max_array_size = dims.iter().copied().reduce(|a, b| a * b).unwrap_or(1);
-
-    let mut current_size = max_array_size;
-    let mut run_lengths = Vec::with_capacity(dims.len());
-    for (flag, dim) in flags.iter().zip(dims.iter()) {
+    // Each run-length is the product of the sizes of the varied dimensions
+    // after it, which is computed from the right so that a dimension without
+    // any values is never divided by.
+    let mut run_lengths = vec![0; sizes.len()];
+    let mut current_size = 1;
+    for (idx, (flag, size)) in flags.iter().zip(sizes).enumerate().rev() {
         if *flag {
-            let run_length = current_size / *dim;
-            run_lengths.push(run_length);
-            current_size = run_length;
-        } else {
-            run_lengths.push(0);
+            run_lengths[idx] = current_size;
+            current_size *= *size;
         }
     }
     run_lengths
@@ -1,14 +1,31 @@
+use std::fmt;
+
+use bitvec::vec::BitVec;
 use flags::Flags;
-use possible_dimensions::PossibleDimensions;
+use possible_dimensions::{PossibleDimensions, combine_dimensions};
 
-use crate::query::Query;
+use crate::{
+    error::{Error, Result},
+    query::Query,
+};
 
+mod dimension_value;
 mod flags;
+mod period;
 mod possible_dimensions;
 
+pub use dimension_value::{DimensionValue, ValueKind};
+pub use period::{Frequency, Period};
+pub use possible_dimensions::DimensionValues;
+
 /// Holds meta-data that allows the actual data
 /// array to be interpreted.
 #[derive(PartialEq, Eq, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "GranularityRepr")
+)]
 pub struct Granularity {
     /// The dimensions that the data actually "varies by".
     flags: Flags,
@@ -18,17 +35,77 @@ pub struct Granularity {
 }
 
 impl Granularity {
-    pub fn new(dimension_name: String, dimension_values: Vec<String>) -> Self {
+    pub fn new<V: Into<DimensionValue>>(dimension_name: String, dimension_values: Vec<V>) -> Self {
+        let dimension_values = dimension_values.into_iter().map(Into::into).collect();
         Self {
             flags: Default::default(),
             dims: PossibleDimensions::default().add_dimension(dimension_name, dimension_values),
         }
     }
 
+    /// Creates a new `Granularity` from a list of dimensions, their values and
+    /// whether the data varies by them.
+    ///
+    /// Dimensions are ordered by cardinality, the same way that
+    /// `combine_dimensions` orders them.
+    pub(crate) fn from_dimensions(mut dimensions: Vec<(String, DimensionValues, bool)>) -> Self {
+        dimensions.sort_by(|(l_name, l_values, _), (r_name, r_values, _)| {
+            (l_values.len(), l_name).cmp(&(r_values.len(), r_name))
+        });
+
+        let mut dims = PossibleDimensions::default();
+        let mut flags = BitVec::with_capacity(dimensions.len());
+        for (name, values, varies) in dimensions {
+            dims = dims.add_dimension(name, values.iter().cloned().collect());
+            flags.push(varies);
+        }
+        let flags = Flags::new(flags, &dims.sizes());
+        Self { flags, dims }
+    }
+
     pub fn size(&self) -> usize {
         self.flags.size()
     }
 
+    /// Returns the total number of cells, i.e. the length of the `values` array.
+    pub fn len(&self) -> usize {
+        self.shape().iter().product()
+    }
+
+    /// Indicates if there are no cells, which happens when a dimension that
+    /// the data varies by has no values.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of values in each dimension that the data varies by,
+    /// in the order they are laid out.
+    pub fn shape(&self) -> Vec<usize> {
+        self.dimensions().map(|(_, values)| values.len()).collect()
+    }
+
+    /// Iterates over the name and values of each dimension that the data
+    /// varies by, in the order they are laid out.
+    ///
+    /// The first dimension has the longest run-length.
+    pub fn dimensions(&self) -> impl Iterator<Item = (&str, &DimensionValues)> {
+        self.dims
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| self.flags.varies_by(*idx))
+            .map(|(_, (name, values))| (name.as_str(), values))
+    }
+
+    /// Iterates over the name and values of each dimension that is possible
+    /// but that the data does not vary by.
+    pub fn possible_but_unused_dimensions(&self) -> impl Iterator<Item = (&str, &DimensionValues)> {
+        self.dims
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| !self.flags.varies_by(*idx))
+            .map(|(_, (name, values))| (name.as_str(), values))
+    }
+
     pub fn flags(&self) -> &Flags {
         &self.flags
     }
@@ -50,7 +127,107 @@ impl Granularity {
         self.flags.run_length(idx)
     }
 
+    /// Returns the possible values of the dimension `dimension_name`.
+    pub fn dimension_values(&self, dimension_name: &str) -> Option<&DimensionValues> {
+        self.dims.get(dimension_name)
+    }
+
+    /// Returns each dimension, its possible values and whether the data
+    /// varies by it.
+    pub(crate) fn dimensions_with_flags(&self) -> Vec<(String, DimensionValues, bool)> {
+        self.dims
+            .iter()
+            .enumerate()
+            .map(|(idx, (name, values))| (name.clone(), values.clone(), self.flags.varies_by(idx)))
+            .collect()
+    }
+
+    /// Calls `f` with the index into each dimension's possible values for every
+    /// cell, in the order the cells are laid out in memory.
+    ///
+    /// Dimensions that the data does not vary by always have an index of `0`.
+    pub(crate) fn for_each_cell(&self, mut f: impl FnMut(&[usize])) {
+        let mut cells = self.cell_indices();
+        while let Some(index) = cells.advance() {
+            f(index);
+        }
+    }
+
+    /// Returns a cursor over the index into each dimension's possible values for
+    /// every cell, see `for_each_cell`.
+    pub(crate) fn cell_indices(&self) -> CellIndices {
+        let sizes = self.dims.sizes();
+        let varied: Vec<usize> = (0..sizes.len())
+            .filter(|idx| self.flags.varies_by(*idx))
+            .collect();
+        let finished = varied.iter().any(|idx| sizes[*idx] == 0);
+        CellIndices {
+            index: vec![0; sizes.len()],
+            sizes,
+            varied,
+            started: false,
+            finished,
+        }
+    }
+
+    /// Returns the name and value of each dimension that the data varies by for
+    /// the cell at `index`.
+    pub(crate) fn coordinate(&self, index: &[usize]) -> Vec<(&str, &DimensionValue)> {
+        self.dims
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| self.flags.varies_by(*idx))
+            .map(|(idx, (name, values))| {
+                let value = values
+                    .get(index[idx])
+                    .expect("Index is within the dimension");
+                (name.as_str(), value)
+            })
+            .collect()
+    }
+
+    /// Returns the run-length in `self` of each dimension in `other`.
+    ///
+    /// The run-length is `0` for dimensions that `self` does not vary by, which
+    /// means that the offset of a cell of `other` within `self` is the sum of the
+    /// products of the cell's indices and these run-lengths.
+    pub(crate) fn run_lengths_for(&self, other: &Self) -> Vec<usize> {
+        other
+            .dims
+            .iter()
+            .map(|(name, _)| match self.dims.maybe_index_of(name) {
+                Some(idx) => *self.flags.run_length(idx),
+                None => 0,
+            })
+            .collect()
+    }
+
+    /// Returns the granularity that varies by every dimension that `self` or
+    /// `other` varies by.
+    ///
+    /// # Panics
+    ///
+    /// If a dimension has different values in `self` and `other`, see
+    /// `try_broadcast`.
     pub fn broadcast(&self, other: &Self) -> Self {
+        self.try_broadcast(other)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `broadcast` but returns an error, rather than panicking, if a
+    /// dimension has different values in `self` and `other`.
+    pub fn try_broadcast(&self, other: &Self) -> Result<Self> {
+        for (name, values) in self.dims.iter() {
+            if other.dims.get(name).is_some_and(|other| other != values) {
+                return Err(Error::ConflictingDimensionValues(name.clone()));
+            }
+        }
+        Ok(self.combine(other))
+    }
+
+    /// Combines the dimensions of `self` and `other`, which must have the
+    /// same values in each dimension they share.
+    fn combine(&self, other: &Self) -> Self {
         if self.dims == other.dims {
             let flags = self.flags.broadcast(&other.flags, &self.dims.sizes());
             Self {
@@ -58,13 +235,66 @@ impl Granularity {
                 dims: self.dims.clone(),
             }
         } else {
-            todo!()
+            let dims = combine_dimensions(&self.dims, &other.dims);
+            let flags = dims
+                .iter()
+                .map(|(name, _)| self.maybe_varies_by(name) || other.maybe_varies_by(name))
+                .collect::<BitVec>();
+            let flags = Flags::new(flags, &dims.sizes());
+            Self { flags, dims }
         }
     }
 
+    /// Indicates if the data varies by `dimension_name`, returning `false` if the
+    /// dimension does not exist.
+    pub(crate) fn maybe_varies_by(&self, dimension_name: &str) -> bool {
+        self.dims
+            .maybe_index_of(dimension_name)
+            .is_some_and(|idx| self.flags.varies_by(idx))
+    }
+
+    /// Checks that the flags and run-lengths are consistent with the possible
+    /// dimensions and that each dimension holds a single kind of value.
+    #[cfg(feature = "serde")]
+    pub(crate) fn validate(&self) -> Result<()> {
+        for (name, values) in self.dims.iter() {
+            if !values.is_homogeneous() {
+                return Err(Error::InvalidGranularity(format!(
+                    "dimension '{}' has values of more than one kind",
+                    name
+                )));
+            }
+        }
+        if !self.flags.is_consistent(&self.dims.sizes()) {
+            return Err(Error::InvalidGranularity(
+                "the flags and run-lengths do not match the dimensions".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks that data with this granularity can be expanded to `target`.
+    ///
+    /// Every dimension that `self` varies by must also be varied by `target`
+    /// with the same values.
+    pub(crate) fn check_expands_to(&self, target: &Self) -> Result<()> {
+        for (idx, (name, values)) in self.dims.iter().enumerate() {
+            if !self.flags.varies_by(idx) {
+                continue;
+            }
+            if !target.maybe_varies_by(name) {
+                return Err(Error::MissingDimension(name.clone()));
+            }
+            if target.dims.get(name) != Some(values) {
+                return Err(Error::ConflictingDimensionValues(name.clone()));
+            }
+        }
+        Ok(())
+    }
+
     pub fn drop(&mut self, dimension_name: &str) {
         let idx = self.dims.index_of(dimension_name);
-        self.flags.drop(idx);
+        self.flags.drop(idx, &self.dims.sizes());
     }
 
     /// Calculates the offsets into each dimensions possible values required
@@ -101,3 +331,161 @@ impl Granularity {
             .unwrap_or(0)
     }
 }
+
+/// The serialized form of `Granularity`, which is validated on deserialization.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct GranularityRepr {
+    flags: Flags,
+    dims: PossibleDimensions,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<GranularityRepr> for Granularity {
+    type Error = Error;
+
+    fn try_from(repr: GranularityRepr) -> Result<Self> {
+        let granularity = Self {
+            flags: repr.flags,
+            dims: repr.dims,
+        };
+        granularity.validate()?;
+        Ok(granularity)
+    }
+}
+
+impl fmt::Debug for Granularity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        /// Shows a single dimension alongside its flag and run-length.
+        struct Dimension<'a> {
+            name: &'a str,
+            values: Vec<String>,
+            varies_by: bool,
+            run_length: usize,
+        }
+
+        impl fmt::Debug for Dimension<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_struct("Dimension")
+                    .field("name", &self.name)
+                    .field("values", &self.values)
+                    .field("varies_by", &self.varies_by)
+                    .field("run_length", &self.run_length)
+                    .finish()
+            }
+        }
+
+        let dimensions: Vec<_> = self
+            .dims
+            .iter()
+            .enumerate()
+            .map(|(idx, (name, values))| Dimension {
+                name,
+                values: values.iter().map(|v| v.to_string()).collect(),
+                varies_by: self.flags.varies_by(idx),
+                run_length: *self.flags.run_length(idx),
+            })
+            .collect();
+        f.debug_struct("Granularity")
+            .field("dimensions", &dimensions)
+            .finish()
+    }
+}
+
+/// A cursor over the index into each dimension's possible values for every
+/// cell of a `Granularity`, in the order the cells are laid out in memory.
+pub struct CellIndices {
+    /// The current index into each dimension.
+    index: Vec<usize>,
+
+    /// The size of each dimension.
+    sizes: Vec<usize>,
+
+    /// The positions of the dimensions that are varied by.
+    varied: Vec<usize>,
+
+    started: bool,
+    finished: bool,
+}
+
+impl CellIndices {
+    /// Moves to the next cell, returning its index.
+    pub(crate) fn advance(&mut self) -> Option<&[usize]> {
+        if self.finished {
+            return None;
+        }
+        if !self.started {
+            self.started = true;
+            return Some(&self.index);
+        }
+
+        // The last dimension has a run-length of one so it changes fastest.
+        for &dim in self.varied.iter().rev() {
+            self.index[dim] += 1;
+            if self.index[dim] < self.sizes[dim] {
+                return Some(&self.index);
+            }
+            self.index[dim] = 0;
+        }
+        self.finished = true;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Data, error::Error, operators::mul};
+
+    #[test]
+    fn test_introspection() {
+        let seasonality = Data::new(
+            "quarter".to_string(),
+            vec!["Q1", "Q2", "Q3", "Q4"],
+            vec![0.2, 0.3, 0.3, 0.2],
+        );
+        let sales = Data::new(
+            "channel".to_string(),
+            vec!["Online", "Retail"],
+            vec![5.0, 8.0],
+        );
+        let forecast = mul(&seasonality, &sales);
+        let granularity = forecast.granularity();
+
+        let names: Vec<_> = granularity.dimensions().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["channel", "quarter"]);
+        assert_eq!(granularity.shape(), vec![2, 4]);
+        assert_eq!(granularity.len(), 8);
+        assert_eq!(granularity.possible_but_unused_dimensions().count(), 0);
+
+        let quarters = granularity.dimension_values("quarter").unwrap();
+        let labels: Vec<_> = quarters.iter().map(|v| v.to_string()).collect();
+        assert_eq!(labels, vec!["Q1", "Q2", "Q3", "Q4"]);
+    }
+
+    #[test]
+    fn test_possible_but_unused_dimensions() {
+        let region = Data::new("region".to_string(), vec!["EU", "US"], vec![1.0, 2.0]);
+        let product = Data::new("product".to_string(), vec!["A", "B", "C"], vec![0.0; 3]);
+        let target = region.granularity().broadcast(product.granularity());
+        let squeezed = region.expand_to(&target).unwrap().squeeze();
+        let granularity = squeezed.granularity();
+
+        let unused: Vec<_> = granularity
+            .possible_but_unused_dimensions()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(unused, vec!["product"]);
+        assert_eq!(granularity.shape(), vec![2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Dimension 'region' has conflicting values.")]
+    fn test_broadcast_conflicting_values() {
+        let eu = Data::new("region".to_string(), vec!["EU"], vec![1.0]);
+        let both = Data::new("region".to_string(), vec!["EU", "US"], vec![1.0, 2.0]);
+        let result = eu.granularity().try_broadcast(both.granularity());
+        assert!(matches!(result, Err(Error::ConflictingDimensionValues(_))));
+
+        eu.granularity().broadcast(both.granularity());
+    }
+}
@@ -0,0 +1,447 @@
+//! Contains the implementation of the `Period` type.
+//!
+//! A `Period` is a month, quarter or year.  Periods can be aligned to
+//! the calendar year or to a fiscal year that starts in any month.  A
+//! fiscal year is named after the calendar year in which it ends, so
+//! with a July start `FY2024` runs from July 2023 to June 2024.
+
+use std::{fmt, str::FromStr};
+
+use chrono::{Datelike, NaiveDate};
+
+use crate::error::{Error, Result};
+
+use super::dimension_value::ValueKind;
+
+/// The length of a `Period`.
+///
+/// Frequencies are ordered from finest to coarsest.  Daily time
+/// dimensions are represented by dates rather than periods, see
+/// `ResampleTo`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Frequency {
+    Monthly,
+    Quarterly,
+    Yearly,
+}
+
+impl Frequency {
+    /// Returns the number of months in a single period.
+    pub fn months(&self) -> u32 {
+        match self {
+            Frequency::Monthly => 1,
+            Frequency::Quarterly => 3,
+            Frequency::Yearly => 12,
+        }
+    }
+
+    /// Returns the number of periods in a single year.
+    pub fn periods_per_year(&self) -> u32 {
+        12 / self.months()
+    }
+}
+
+/// A month, quarter or year within a calendar or fiscal year.
+///
+/// Periods of the same `Frequency` and fiscal year start are ordered
+/// chronologically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "PeriodRepr")
+)]
+pub struct Period {
+    /// The length of the period.
+    frequency: Frequency,
+
+    /// The month (`1` to `12`) in which the year starts.
+    fiscal_year_start: u32,
+
+    /// The (fiscal) year the period belongs to.
+    year: i32,
+
+    /// The 1-based position of the period within its year.
+    number: u32,
+}
+
+impl Period {
+    /// Creates a new period aligned to the calendar year.
+    ///
+    /// # Panics
+    ///
+    /// If `number` is not a valid period within a year of `frequency`.
+    pub fn new(frequency: Frequency, year: i32, number: u32) -> Self {
+        Self::fiscal(frequency, year, number, 1)
+    }
+
+    /// Creates a new period within a fiscal year starting in the month
+    /// `fiscal_year_start`.
+    ///
+    /// # Panics
+    ///
+    /// If `number` is not a valid period within a year of `frequency` or
+    /// `fiscal_year_start` is not a valid month.
+    pub fn fiscal(frequency: Frequency, year: i32, number: u32, fiscal_year_start: u32) -> Self {
+        assert!(
+            is_month(fiscal_year_start),
+            "Invalid fiscal year start month: {}",
+            fiscal_year_start
+        );
+        assert!(
+            is_period_number(frequency, number),
+            "Invalid period number {} for {:?} periods",
+            number,
+            frequency
+        );
+        Self {
+            frequency,
+            fiscal_year_start,
+            year,
+            number,
+        }
+    }
+
+    /// Creates a calendar month.
+    pub fn monthly(year: i32, month: u32) -> Self {
+        Self::new(Frequency::Monthly, year, month)
+    }
+
+    /// Creates a calendar quarter.
+    pub fn quarterly(year: i32, quarter: u32) -> Self {
+        Self::new(Frequency::Quarterly, year, quarter)
+    }
+
+    /// Creates a calendar year.
+    pub fn yearly(year: i32) -> Self {
+        Self::new(Frequency::Yearly, year, 1)
+    }
+
+    /// Returns the period of `frequency` that contains `date`.
+    pub fn containing(date: NaiveDate, frequency: Frequency, fiscal_year_start: u32) -> Self {
+        let months_into_year = (date.month() + 12 - fiscal_year_start) % 12;
+        let year = if fiscal_year_start == 1 || date.month() < fiscal_year_start {
+            date.year()
+        } else {
+            date.year() + 1
+        };
+        let number = months_into_year / frequency.months() + 1;
+        Self::fiscal(frequency, year, number, fiscal_year_start)
+    }
+
+    pub fn frequency(&self) -> Frequency {
+        self.frequency
+    }
+
+    pub fn fiscal_year_start(&self) -> u32 {
+        self.fiscal_year_start
+    }
+
+    pub fn year(&self) -> i32 {
+        self.year
+    }
+
+    pub fn number(&self) -> u32 {
+        self.number
+    }
+
+    /// Returns the first day of the period.
+    pub fn first_day(&self) -> NaiveDate {
+        // Fiscal years that don't start in January begin in the prior calendar year.
+        let start_year = if self.fiscal_year_start == 1 {
+            self.year
+        } else {
+            self.year - 1
+        };
+        let months = (self.fiscal_year_start - 1) + (self.number - 1) * self.frequency.months();
+        let year = start_year + (months / 12) as i32;
+        let month = months % 12 + 1;
+        NaiveDate::from_ymd_opt(year, month, 1).expect("Period is outside the supported range")
+    }
+
+    /// Returns the last day of the period.
+    pub fn last_day(&self) -> NaiveDate {
+        self.succ().first_day().pred_opt().unwrap()
+    }
+
+    /// Returns the number of days in the period.
+    pub fn days(&self) -> i64 {
+        (self.succ().first_day() - self.first_day()).num_days()
+    }
+
+    /// Indicates if `date` falls within the period.
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.first_day() <= date && date <= self.last_day()
+    }
+
+    /// Returns the following period.
+    pub fn succ(&self) -> Self {
+        if self.number == self.frequency.periods_per_year() {
+            Self {
+                year: self.year + 1,
+                number: 1,
+                ..*self
+            }
+        } else {
+            Self {
+                number: self.number + 1,
+                ..*self
+            }
+        }
+    }
+
+    /// Returns the preceding period.
+    pub fn pred(&self) -> Self {
+        if self.number == 1 {
+            Self {
+                year: self.year - 1,
+                number: self.frequency.periods_per_year(),
+                ..*self
+            }
+        } else {
+            Self {
+                number: self.number - 1,
+                ..*self
+            }
+        }
+    }
+
+    /// Parses a period in the format produced by `Display`.
+    ///
+    /// Fiscal periods (prefixed with `FY`) are only accepted when
+    /// `fiscal_year_start` is not January and calendar periods only when it is.
+    pub fn parse(s: &str, fiscal_year_start: u32) -> Result<Self> {
+        let invalid = || Error::InvalidDimensionValue {
+            value: s.to_string(),
+            kind: ValueKind::Period,
+        };
+        if !is_month(fiscal_year_start) {
+            return Err(invalid());
+        }
+
+        let trimmed = s.trim();
+        let body = match trimmed.strip_prefix("FY") {
+            Some(body) if fiscal_year_start != 1 => body,
+            None if fiscal_year_start == 1 => trimmed,
+            _ => return Err(invalid()),
+        };
+        let (year, rest) = match body.split_once('-') {
+            Some((year, rest)) => (year, Some(rest)),
+            None => (body, None),
+        };
+        let year: i32 = year.parse().map_err(|_| invalid())?;
+
+        let (frequency, number) = match rest {
+            None => (Frequency::Yearly, 1),
+            Some(rest) => {
+                let (frequency, number) = if let Some(q) = rest.strip_prefix('Q') {
+                    (Frequency::Quarterly, q)
+                } else if fiscal_year_start != 1 {
+                    (
+                        Frequency::Monthly,
+                        rest.strip_prefix('M').ok_or_else(invalid)?,
+                    )
+                } else {
+                    (Frequency::Monthly, rest)
+                };
+                (frequency, number.parse().map_err(|_| invalid())?)
+            }
+        };
+        if !is_period_number(frequency, number) {
+            return Err(invalid());
+        }
+        Ok(Self::fiscal(frequency, year, number, fiscal_year_start))
+    }
+
+    /// Returns all the periods from `start` to `end` (inclusive).
+    ///
+    /// # Panics
+    ///
+    /// If `start` and `end` do not have the same frequency and fiscal year start.
+    pub fn range(start: Period, end: Period) -> impl Iterator<Item = Period> {
+        assert_eq!(
+            (start.frequency, start.fiscal_year_start),
+            (end.frequency, end.fiscal_year_start),
+            "Periods in a range must be of the same kind"
+        );
+        std::iter::successors(Some(start), |p| Some(p.succ())).take_while(move |p| *p <= end)
+    }
+}
+
+fn is_month(month: u32) -> bool {
+    (1..=12).contains(&month)
+}
+
+fn is_period_number(frequency: Frequency, number: u32) -> bool {
+    (1..=frequency.periods_per_year()).contains(&number)
+}
+
+/// The serialized form of `Period`, which is validated on deserialization.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct PeriodRepr {
+    frequency: Frequency,
+    fiscal_year_start: u32,
+    year: i32,
+    number: u32,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<PeriodRepr> for Period {
+    type Error = Error;
+
+    /// Applies the same checks as `Period::fiscal`.
+    fn try_from(repr: PeriodRepr) -> Result<Self> {
+        if !is_month(repr.fiscal_year_start) || !is_period_number(repr.frequency, repr.number) {
+            return Err(Error::InvalidDimensionValue {
+                value: format!(
+                    "{:?} period {} of {} with a fiscal year starting in month {}",
+                    repr.frequency, repr.number, repr.year, repr.fiscal_year_start
+                ),
+                kind: ValueKind::Period,
+            });
+        }
+        Ok(Self::fiscal(
+            repr.frequency,
+            repr.year,
+            repr.number,
+            repr.fiscal_year_start,
+        ))
+    }
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = if self.fiscal_year_start == 1 {
+            ""
+        } else {
+            "FY"
+        };
+        match self.frequency {
+            Frequency::Monthly if prefix.is_empty() => {
+                write!(f, "{}-{:02}", self.year, self.number)
+            }
+            Frequency::Monthly => write!(f, "FY{}-M{:02}", self.year, self.number),
+            Frequency::Quarterly => write!(f, "{}{}-Q{}", prefix, self.year, self.number),
+            Frequency::Yearly => write!(f, "{}{}", prefix, self.year),
+        }
+    }
+}
+
+impl FromStr for Period {
+    type Err = Error;
+
+    /// Parses a calendar period, see `Period::parse`.
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "serde")]
+    use crate::DimensionValue;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_calendar_boundaries() {
+        let q = Period::quarterly(2024, 1);
+        assert_eq!(q.first_day(), date(2024, 1, 1));
+        assert_eq!(q.last_day(), date(2024, 3, 31));
+        assert_eq!(q.days(), 91);
+
+        let m = Period::monthly(2023, 12);
+        assert_eq!(m.last_day(), date(2023, 12, 31));
+        assert_eq!(m.succ(), Period::monthly(2024, 1));
+        assert_eq!(Period::monthly(2024, 1).pred(), m);
+    }
+
+    #[test]
+    fn test_fiscal_year() {
+        // FY2024 starting in July runs from July 2023 to June 2024.
+        let fy = Period::fiscal(Frequency::Yearly, 2024, 1, 7);
+        assert_eq!(fy.first_day(), date(2023, 7, 1));
+        assert_eq!(fy.last_day(), date(2024, 6, 30));
+
+        let q3 = Period::fiscal(Frequency::Quarterly, 2024, 3, 7);
+        assert_eq!(q3.first_day(), date(2024, 1, 1));
+        assert_eq!(
+            Period::containing(date(2024, 2, 14), Frequency::Quarterly, 7),
+            q3
+        );
+        assert_eq!(
+            Period::containing(date(2023, 7, 1), Frequency::Monthly, 7),
+            Period::fiscal(Frequency::Monthly, 2024, 1, 7)
+        );
+    }
+
+    #[test]
+    fn test_ordering_and_display() {
+        let mut periods = [
+            Period::monthly(2024, 10),
+            Period::monthly(2023, 11),
+            Period::monthly(2024, 2),
+        ];
+        periods.sort();
+        let labels: Vec<_> = periods.iter().map(|p| p.to_string()).collect();
+        assert_eq!(labels, vec!["2023-11", "2024-02", "2024-10"]);
+
+        assert_eq!(Period::quarterly(2024, 2).to_string(), "2024-Q2");
+        assert_eq!(
+            Period::fiscal(Frequency::Monthly, 2024, 3, 4).to_string(),
+            "FY2024-M03"
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        for period in [
+            Period::monthly(2024, 3),
+            Period::quarterly(2023, 4),
+            Period::yearly(2025),
+        ] {
+            assert_eq!(period.to_string().parse::<Period>().unwrap(), period);
+        }
+
+        let fiscal = Period::fiscal(Frequency::Monthly, 2024, 3, 7);
+        assert_eq!(Period::parse(&fiscal.to_string(), 7).unwrap(), fiscal);
+        assert!(Period::parse(&fiscal.to_string(), 1).is_err());
+        assert!("2024-13".parse::<Period>().is_err());
+    }
+
+    #[test]
+    fn test_range() {
+        let range: Vec<_> =
+            Period::range(Period::monthly(2023, 11), Period::monthly(2024, 2)).collect();
+        assert_eq!(range.len(), 4);
+        assert_eq!(range[3], Period::monthly(2024, 2));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize_is_validated() {
+        let json = serde_json::to_string(&Period::monthly(2024, 3)).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Period>(&json).unwrap(),
+            Period::monthly(2024, 3)
+        );
+
+        for invalid in [
+            r#"{"frequency":"Daily","fiscal_year_start":1,"year":2024,"number":1}"#,
+            r#"{"frequency":"Monthly","fiscal_year_start":1,"year":2024,"number":0}"#,
+            r#"{"frequency":"Monthly","fiscal_year_start":13,"year":2024,"number":1}"#,
+        ] {
+            assert!(serde_json::from_str::<Period>(invalid).is_err());
+        }
+        let value =
+            r#"{"Period":{"frequency":"Quarterly","fiscal_year_start":1,"year":2024,"number":5}}"#;
+        assert!(serde_json::from_str::<DimensionValue>(value).is_err());
+        let valid = value.replace("\"number\":5", "\"number\":4");
+        assert!(serde_json::from_str::<DimensionValue>(&valid).is_ok());
+    }
+}
@@ -5,16 +5,102 @@
 //!
 //! How dimensions are ordered is encoded in this type.  Currently,
 //! higher cardinality dimensions are push toward the right which should
-//! allow zero copy slicing larger regions of data.
+//! allow zero copy slicing larger regions of data.  Dimensions with the
+//! same cardinality are ordered by name, e.g. `region` with the values
+//! `EU` and `US` is the outermost dimension of data that also varies by
+//! `product` with the values `A`, `B` and `C`, or by `scenario` with the
+//! values `base` and `downside`.
 
 use indexmap::IndexMap;
 
+use super::dimension_value::DimensionValue;
+
 /// Holds the actual values that are possible within a dimension.
 #[derive(Eq, PartialEq, Clone, Default, Debug)]
-pub struct DimensionValues(Vec<String>);
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct DimensionValues(Vec<DimensionValue>);
+
+impl DimensionValues {
+    /// Creates a new set of dimension values.
+    ///
+    /// # Panics
+    ///
+    /// If `values` contains values of more than one kind.
+    pub fn new(values: Vec<DimensionValue>) -> Self {
+        if let Some(first) = values.first() {
+            let kind = first.kind();
+            assert!(
+                values.iter().all(|v| v.kind() == kind),
+                "Dimension values must all be of the same kind ({:?})",
+                kind
+            );
+        }
+        Self(values)
+    }
+
+    /// Creates a new set of dimension values, or returns `None` if `values`
+    /// contains values of more than one kind.
+    pub(crate) fn try_new(values: Vec<DimensionValue>) -> Option<Self> {
+        let values = Self(values);
+        values.is_homogeneous().then_some(values)
+    }
+
+    /// Returns the number of values in the dimension.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the value at position `idx`.
+    pub fn get(&self, idx: usize) -> Option<&DimensionValue> {
+        self.0.get(idx)
+    }
+
+    /// Indicates if every value is of the same kind.
+    pub(crate) fn is_homogeneous(&self) -> bool {
+        self.0.windows(2).all(|w| w[0].kind() == w[1].kind())
+    }
+
+    /// Returns the position of `value` within the dimension.
+    pub fn position(&self, value: &DimensionValue) -> Option<usize> {
+        self.0.iter().position(|v| v == value)
+    }
+
+    /// Iterates over the values in the order they are laid out.
+    pub fn iter(&self) -> std::slice::Iter<'_, DimensionValue> {
+        self.0.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a DimensionValues {
+    type Item = &'a DimensionValue;
+    type IntoIter = std::slice::Iter<'a, DimensionValue>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl From<Vec<DimensionValue>> for DimensionValues {
+    fn from(values: Vec<DimensionValue>) -> Self {
+        Self::new(values)
+    }
+}
 
 /// The collection of all possible dimensions that a value **could** vary by.
 #[derive(Default, Eq, PartialEq, Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct PossibleDimensions(IndexMap<String, DimensionValues>);
 
 impl PossibleDimensions {
@@ -25,7 +111,7 @@ impl PossibleDimensions {
             .unwrap_or_else(|| panic!("Un-recognised dimension: '{}'", dimension_name))
     }
 
-    pub fn index_of_value(&self, dim_index: usize, value: &String) -> usize {
+    pub fn index_of_value(&self, dim_index: usize, value: &DimensionValue) -> usize {
         let values = &self.0[dim_index];
         values.0.iter().position(|s| s == value).unwrap()
     }
@@ -36,19 +122,28 @@ impl PossibleDimensions {
     }
 
     /// Builder type API for adding new dimensions.
-    pub fn add_dimension(mut self, name: String, values: Vec<String>) -> Self {
-        self.0.insert(name, DimensionValues(values));
+    pub fn add_dimension(mut self, name: String, values: Vec<DimensionValue>) -> Self {
+        self.0.insert(name, DimensionValues::new(values));
         self
     }
 
     pub fn sizes(&self) -> Vec<usize> {
         self.0.values().map(|v| v.0.len()).collect()
     }
+
+    /// Returns the values of the dimension with name `dimension_name`.
+    pub fn get(&self, dimension_name: &str) -> Option<&DimensionValues> {
+        self.0.get(dimension_name)
+    }
+
+    /// Iterates over the name and values of each dimension in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &DimensionValues)> {
+        self.0.iter()
+    }
 }
 
 /// Combines two instances of `PossibleDimensions` creating a new `PossibleDimenions` that
 /// contains all the dimensions of `lhs` and `lhs`.
-#[allow(dead_code)]
 pub(crate) fn combine_dimensions(
     lhs: &PossibleDimensions,
     rhs: &PossibleDimensions,
@@ -80,7 +175,7 @@ pub(crate) fn combine_dimensions(
             }
             (Some((name, values)), None) => {
                 let _ = new_possible_dimensions.insert(name.clone(), values.clone());
-                for (k, v) in rhs_iter {
+                for (k, v) in lhs_iter {
                     let _ = new_possible_dimensions.insert(k.clone(), v.clone());
                 }
                 break;
@@ -123,9 +218,9 @@ mod tests {
     #[test]
     fn test_same_single_dimension() {
         let a = PossibleDimensions::default()
-            .add_dimension("1".to_string(), vec!["a".to_string(), "b".to_string()]);
+            .add_dimension("1".to_string(), vec!["a".into(), "b".into()]);
         let b = PossibleDimensions::default()
-            .add_dimension("1".to_string(), vec!["a".to_string(), "b".to_string()]);
+            .add_dimension("1".to_string(), vec!["a".into(), "b".into()]);
 
         let c = combine_dimensions(&a, &b);
 
@@ -136,11 +231,11 @@ mod tests {
     #[test]
     fn test_same_multiple_dimension() {
         let a = PossibleDimensions::default()
-            .add_dimension("1".to_string(), vec!["a".to_string(), "b".to_string()])
-            .add_dimension("2".to_string(), vec!["a".to_string(), "b".to_string()]);
+            .add_dimension("1".to_string(), vec!["a".into(), "b".into()])
+            .add_dimension("2".to_string(), vec!["a".into(), "b".into()]);
         let b = PossibleDimensions::default()
-            .add_dimension("1".to_string(), vec!["a".to_string(), "b".to_string()])
-            .add_dimension("2".to_string(), vec!["a".to_string(), "b".to_string()]);
+            .add_dimension("1".to_string(), vec!["a".into(), "b".into()])
+            .add_dimension("2".to_string(), vec!["a".into(), "b".into()]);
 
         let c = combine_dimensions(&a, &b);
 
@@ -152,20 +247,20 @@ mod tests {
     #[should_panic]
     fn test_values_differ() {
         let a = PossibleDimensions::default()
-            .add_dimension("1".to_string(), vec!["a".to_string(), "b".to_string()]);
+            .add_dimension("1".to_string(), vec!["a".into(), "b".into()]);
         let b = PossibleDimensions::default()
-            .add_dimension("1".to_string(), vec!["a".to_string(), "c".to_string()]);
+            .add_dimension("1".to_string(), vec!["a".into(), "c".into()]);
         let _ = combine_dimensions(&a, &b);
     }
 
     #[test]
     fn test_expand() {
         let a = PossibleDimensions::default()
-            .add_dimension("1".to_string(), vec!["a".to_string(), "b".to_string()])
-            .add_dimension("2".to_string(), vec!["c".to_string(), "d".to_string()]);
+            .add_dimension("1".to_string(), vec!["a".into(), "b".into()])
+            .add_dimension("2".to_string(), vec!["c".into(), "d".into()]);
         let b = PossibleDimensions::default()
-            .add_dimension("1".to_string(), vec!["a".to_string(), "b".to_string()])
-            .add_dimension("3".to_string(), vec!["e".to_string(), "f".to_string()]);
+            .add_dimension("1".to_string(), vec!["a".into(), "b".into()])
+            .add_dimension("3".to_string(), vec!["e".into(), "f".into()]);
 
         let c = combine_dimensions(&a, &b);
         assert_eq!(c.0.len(), 3);
@@ -173,29 +268,38 @@ mod tests {
         // First dimension
         let (key, values) = c.0.get_index(0).unwrap();
         assert_eq!(key, "1");
-        assert_eq!(values.0, vec!["a", "b"]);
+        assert_eq!(values.0, vec!["a".into(), "b".into()]);
 
         // Second dimension
         let (key, values) = c.0.get_index(1).unwrap();
         assert_eq!(key, "2");
-        assert_eq!(values.0, vec!["c", "d"]);
+        assert_eq!(values.0, vec!["c".into(), "d".into()]);
 
         // Third dimension
         let (key, values) = c.0.get_index(2).unwrap();
         assert_eq!(key, "3");
-        assert_eq!(values.0, vec!["e", "f"]);
+        assert_eq!(values.0, vec!["e".into(), "f".into()]);
+    }
+
+    #[test]
+    fn test_remaining_lhs_dimensions() {
+        let a = PossibleDimensions::default()
+            .add_dimension("1".to_string(), vec!["a".into(), "b".into()])
+            .add_dimension("2".to_string(), vec!["c".into(), "d".into(), "e".into()]);
+        let b = PossibleDimensions::default()
+            .add_dimension("1".to_string(), vec!["a".into(), "b".into()]);
+
+        let c = combine_dimensions(&a, &b);
+        assert_eq!(c, a);
     }
 
     #[test]
     fn test_sort_by_cardinality() {
         let a = PossibleDimensions::default()
-            .add_dimension("2".to_string(), vec!["c".to_string(), "d".to_string()])
-            .add_dimension(
-                "1".to_string(),
-                vec!["a".to_string(), "b".to_string(), "c".to_string()],
-            );
+            .add_dimension("2".to_string(), vec!["c".into(), "d".into()])
+            .add_dimension("1".to_string(), vec!["a".into(), "b".into(), "c".into()]);
         let b = PossibleDimensions::default()
-            .add_dimension("3".to_string(), vec!["e".to_string(), "f".to_string()]);
+            .add_dimension("3".to_string(), vec!["e".into(), "f".into()]);
 
         let c = combine_dimensions(&a, &b);
         dbg!(&c);
@@ -204,16 +308,16 @@ mod tests {
         // First dimension
         let (key, values) = c.0.get_index(0).unwrap();
         assert_eq!(key, "2");
-        assert_eq!(values.0, vec!["c", "d"]);
+        assert_eq!(values.0, vec!["c".into(), "d".into()]);
 
         // Second dimension
         let (key, values) = c.0.get_index(1).unwrap();
         assert_eq!(key, "3");
-        assert_eq!(values.0, vec!["e", "f"]);
+        assert_eq!(values.0, vec!["e".into(), "f".into()]);
 
         // Third dimension
         let (key, values) = c.0.get_index(2).unwrap();
         assert_eq!(key, "1");
-        assert_eq!(values.0, vec!["a", "b", "c"]);
+        assert_eq!(values.0, vec!["a".into(), "b".into(), "c".into()]);
     }
 }
@@ -0,0 +1,349 @@
+//! Contains reading and writing of `Data` as CSV files.
+//!
+//! Two layouts are supported.  The "long" layout has a column per
+//! dimension and a column of values, with one row per cell.  The "wide"
+//! layout spreads one dimension across the column headers, with one row
+//! per combination of the remaining dimensions.
+//!
+//! Empty value fields are read as `NaN` and `NaN` values are written as
+//! empty fields.
+
+use std::{
+    collections::HashSet,
+    io::{Read, Write},
+};
+
+use csv::{ReaderBuilder, StringRecord, Writer};
+
+use crate::{
+    Data, DimensionValue,
+    error::{Error, Result},
+    granularity::ValueKind,
+};
+
+impl Data {
+    /// Reads data in the long layout from `reader`.
+    ///
+    /// The first line must be a header naming the columns.  The columns in
+    /// `dimensions` are parsed as values of the given kind and
+    /// `value_column` holds the values, any other columns are ignored.
+    pub fn read_csv_long<R: Read>(
+        reader: R,
+        dimensions: &[(&str, ValueKind)],
+        value_column: &str,
+    ) -> Result<Data> {
+        let mut reader = ReaderBuilder::new().from_reader(reader);
+        let headers = reader.headers().map_err(csv_error)?.clone();
+
+        let column_index = |name: &str| {
+            headers
+                .iter()
+                .position(|header| header == name)
+                .ok_or_else(|| Error::Csv {
+                    line: 1,
+                    column: name.to_string(),
+                    reason: "missing column".to_string(),
+                })
+        };
+        let dimension_columns = dimensions
+            .iter()
+            .map(|(name, _)| column_index(name))
+            .collect::<Result<Vec<_>>>()?;
+        let value_index = column_index(value_column)?;
+
+        let mut rows = Vec::new();
+        let mut seen = HashSet::new();
+        for record in reader.records() {
+            let record = record.map_err(csv_error)?;
+            let line = line_of(&record);
+            let coordinate = dimensions
+                .iter()
+                .zip(&dimension_columns)
+                .map(|((name, kind), idx)| parse_dimension_value(&record[*idx], *kind, line, name))
+                .collect::<Result<Vec<_>>>()?;
+            let value = parse_value(&record[value_index], line, value_column)?;
+            if !seen.insert(coordinate.clone()) {
+                return Err(duplicate_cell(line, value_column));
+            }
+            rows.push((coordinate, value));
+        }
+
+        let names: Vec<&str> = dimensions.iter().map(|(name, _)| *name).collect();
+        Data::from_rows(&names, rows)
+    }
+
+    /// Writes the data to `writer` in the long layout.
+    ///
+    /// There is a column for each dimension the data varies by, in layout
+    /// order, followed by a `value` column.
+    pub fn write_csv_long<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = Writer::from_writer(writer);
+
+        let mut header: Vec<&str> = self
+            .granularity
+            .dimensions()
+            .map(|(name, _)| name)
+            .collect();
+        header.push("value");
+        writer.write_record(&header).map_err(csv_error)?;
+
+        for (coordinate, value) in self.iter_cells() {
+            let mut record: Vec<String> = coordinate.iter().map(|(_, v)| v.to_string()).collect();
+            record.push(format_value(value));
+            writer.write_record(&record).map_err(csv_error)?;
+        }
+        writer.flush().map_err(|e| Error::Io(e.to_string()))
+    }
+
+    /// Reads data in the wide layout from `reader`.
+    ///
+    /// The header must start with the names of `row_dimensions`, the
+    /// remaining headers are parsed as the values of `column_dimension`.
+    /// The values of `column_dimension` keep the order of the headers.
+    pub fn read_csv_wide<R: Read>(
+        reader: R,
+        row_dimensions: &[(&str, ValueKind)],
+        column_dimension: (&str, ValueKind),
+    ) -> Result<Data> {
+        let mut reader = ReaderBuilder::new().from_reader(reader);
+        let headers = reader.headers().map_err(csv_error)?.clone();
+
+        for (idx, (name, _)) in row_dimensions.iter().enumerate() {
+            if headers.get(idx) != Some(*name) {
+                return Err(Error::Csv {
+                    line: 1,
+                    column: headers.get(idx).unwrap_or_default().to_string(),
+                    reason: format!("expected the row dimension '{}'", name),
+                });
+            }
+        }
+        let (column_name, column_kind) = column_dimension;
+        let columns = headers
+            .iter()
+            .skip(row_dimensions.len())
+            .map(|header| parse_dimension_value(header, column_kind, 1, header))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut rows = Vec::new();
+        let mut seen = HashSet::new();
+        for record in reader.records() {
+            let record = record.map_err(csv_error)?;
+            let line = line_of(&record);
+            let coordinate = row_dimensions
+                .iter()
+                .zip(record.iter())
+                .map(|((name, kind), field)| parse_dimension_value(field, *kind, line, name))
+                .collect::<Result<Vec<_>>>()?;
+            if !seen.insert(coordinate.clone()) {
+                // Without row dimensions every line holds the same cells.
+                let column = row_dimensions
+                    .first()
+                    .map_or(column_name, |(name, _)| *name);
+                return Err(duplicate_cell(line, column));
+            }
+
+            let fields = record.iter().skip(row_dimensions.len());
+            for ((field, column), header) in fields
+                .zip(&columns)
+                .zip(headers.iter().skip(row_dimensions.len()))
+            {
+                let mut coordinate = coordinate.clone();
+                coordinate.push(column.clone());
+                rows.push((coordinate, parse_value(field, line, header)?));
+            }
+        }
+
+        let mut names: Vec<&str> = row_dimensions.iter().map(|(name, _)| *name).collect();
+        names.push(column_name);
+        Data::from_rows(&names, rows)
+    }
+
+    /// Writes the data to `writer` in the wide layout, with the values of
+    /// `column_dimension` spread across the column headers in their order.
+    ///
+    /// The remaining dimensions the data varies by are written as the
+    /// leading columns, in layout order.
+    pub fn write_csv_wide<W: Write>(&self, writer: W, column_dimension: &str) -> Result<()> {
+        let granularity = &self.granularity;
+        let column_values = granularity
+            .dimensions()
+            .find(|(name, _)| *name == column_dimension)
+            .map(|(_, values)| values)
+            .ok_or_else(|| Error::UnknownDimension(column_dimension.to_string()))?;
+        let column_run_length = *granularity.run_length(column_dimension);
+        let rows: Vec<_> = granularity
+            .dimensions()
+            .filter(|(name, _)| *name != column_dimension)
+            .map(|(name, values)| (name, values, *granularity.run_length(name)))
+            .collect();
+
+        let mut writer = Writer::from_writer(writer);
+        let mut header: Vec<String> = rows.iter().map(|(name, _, _)| name.to_string()).collect();
+        header.extend(column_values.iter().map(|v| v.to_string()));
+        writer.write_record(&header).map_err(csv_error)?;
+
+        let values = self.values().values();
+        let total_rows: usize = rows.iter().map(|(_, values, _)| values.len()).product();
+        for row in 0..total_rows {
+            // Decompose the row number into an index for each row dimension.
+            let mut remainder = row;
+            let mut offset = 0;
+            let mut record = vec![String::new(); rows.len()];
+            for (idx, (_, values, run_length)) in rows.iter().enumerate().rev() {
+                let i = remainder % values.len();
+                remainder /= values.len();
+                offset += i * run_length;
+                record[idx] = values.get(i).unwrap().to_string();
+            }
+            for column in 0..column_values.len() {
+                record.push(format_value(values[offset + column * column_run_length]));
+            }
+            writer.write_record(&record).map_err(csv_error)?;
+        }
+        writer.flush().map_err(|e| Error::Io(e.to_string()))
+    }
+}
+
+fn csv_error(error: csv::Error) -> Error {
+    Error::Io(error.to_string())
+}
+
+/// Returns the line number of `record`, starting from one.
+fn line_of(record: &StringRecord) -> u64 {
+    record.position().map(|p| p.line()).unwrap_or_default()
+}
+
+fn parse_dimension_value(
+    field: &str,
+    kind: ValueKind,
+    line: u64,
+    column: &str,
+) -> Result<DimensionValue> {
+    DimensionValue::parse(field, kind).map_err(|e| Error::Csv {
+        line,
+        column: column.to_string(),
+        reason: e.to_string(),
+    })
+}
+
+fn parse_value(field: &str, line: u64, column: &str) -> Result<f64> {
+    let field = field.trim();
+    if field.is_empty() {
+        return Ok(f64::NAN);
+    }
+    field.parse().map_err(|_| Error::Csv {
+        line,
+        column: column.to_string(),
+        reason: format!("'{}' is not a number", field),
+    })
+}
+
+fn duplicate_cell(line: u64, column: &str) -> Error {
+    Error::Csv {
+        line,
+        column: column.to_string(),
+        reason: "more than one value for the same cell".to_string(),
+    }
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        String::new()
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDE: &str = "\
+product,region,2024-03,2024-01,2024-02
+A,EU,1,2,3
+A,US,4,,6
+B,EU,7,8,9
+B,US,10,11,12
+";
+
+    fn read_wide() -> Data {
+        Data::read_csv_wide(
+            WIDE.as_bytes(),
+            &[
+                ("product", ValueKind::String),
+                ("region", ValueKind::String),
+            ],
+            ("month", ValueKind::Period),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_wide_round_trip() {
+        let data = read_wide();
+        assert_eq!(data.granularity().shape(), vec![2, 2, 3]);
+
+        let mut bytes = Vec::new();
+        data.write_csv_wide(&mut bytes, "month").unwrap();
+        assert_eq!(String::from_utf8(bytes).unwrap(), WIDE);
+    }
+
+    #[test]
+    fn test_long_round_trip() {
+        let data = read_wide();
+        let mut bytes = Vec::new();
+        data.write_csv_long(&mut bytes).unwrap();
+        let long = String::from_utf8(bytes).unwrap();
+        assert!(long.starts_with("product,region,month,value\nA,EU,2024-03,1\n"));
+        assert!(long.contains("A,US,2024-01,\n"));
+
+        let dimensions = [
+            ("region", ValueKind::String),
+            ("product", ValueKind::String),
+            ("month", ValueKind::Period),
+        ];
+        let decoded = Data::read_csv_long(long.as_bytes(), &dimensions, "value").unwrap();
+        assert!(decoded.granularity() == data.granularity());
+        let (decoded, data) = (decoded.values().values(), data.values().values());
+        assert!(
+            decoded
+                .iter()
+                .zip(data.iter())
+                .all(|(a, b)| a == b || (a.is_nan() && b.is_nan()))
+        );
+    }
+
+    #[test]
+    fn test_errors_point_to_field() {
+        let csv = "region,year,value\nEU,2024,1\nUS,twenty,2\n";
+        let dimensions = [("region", ValueKind::String), ("year", ValueKind::Integer)];
+        let error = Data::read_csv_long(csv.as_bytes(), &dimensions, "value").unwrap_err();
+        assert!(matches!(error, Error::Csv { line: 3, column, .. } if column == "year"));
+
+        let csv = "region,2024-01\nEU,1\nUS,x\n";
+        let error = Data::read_csv_wide(
+            csv.as_bytes(),
+            &[("region", ValueKind::String)],
+            ("month", ValueKind::Period),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "CSV line 3, column '2024-01': 'x' is not a number"
+        );
+    }
+
+    #[test]
+    fn test_duplicate_row() {
+        let csv = "region,value\nEU,1\nEU,2\n";
+        let error = Data::read_csv_long(csv.as_bytes(), &[("region", ValueKind::String)], "value")
+            .unwrap_err();
+        assert!(matches!(error, Error::Csv { line: 3, .. }));
+
+        // Without row dimensions the second line repeats the cells of the first.
+        let csv = "a,b\n1,2\n3,4\n";
+        let error =
+            Data::read_csv_wide(csv.as_bytes(), &[], ("letter", ValueKind::String)).unwrap_err();
+        assert!(matches!(error, Error::Csv { line: 3, column, .. } if column == "letter"));
+    }
+}
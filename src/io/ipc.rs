@@ -0,0 +1,317 @@
+//! Contains persistence of `Data` using the Arrow IPC file format,
+//! also known as Feather.
+//!
+//! The dense `values` are stored as-is in a single `Float64` column and
+//! the `Granularity` is encoded as JSON in the schema meta-data, so
+//! reading a file never has to rebuild or re-sort the layout.  Sparse data
+//! is not densified, instead only its stored cells are written, with the
+//! offset of each in an `offsets` column and the fill in the meta-data.
+//! The rest of
+//! the meta-data of the `Data`, e.g. the additivity or the valid
+//! combinations, is encoded alongside it and is optional so older files
+//! can still be read.  Overrides are
+//! stored alongside the values without the scenario dimension, rather than
+//! expanded.
+
+use std::{
+    collections::HashMap,
+    io::{Read, Seek, Write},
+    sync::Arc,
+};
+
+use arrow_array::{
+    Array, ArrayRef, Float64Array, RecordBatch, UInt64Array,
+    cast::AsArray,
+    types::{Float64Type, UInt64Type},
+};
+use arrow_ipc::{reader::FileReader, writer::FileWriter};
+use arrow_schema::{DataType, Field, Schema};
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::{
+    Data, Granularity,
+    error::{Error, Result},
+    scenario::Override,
+    sparse::{SparseValues, Stored},
+};
+
+/// The schema meta-data key that holds the encoded `Granularity`.
+const GRANULARITY_KEY: &str = "grain.granularity";
+
+/// The schema meta-data key that holds the additivity of each dimension.
+const ADDITIVITY_KEY: &str = "grain.additivity";
+
+/// The schema meta-data key that holds the unit of the values, if known.
+const UNIT_KEY: &str = "grain.unit";
+
+/// The schema meta-data key that holds the dimension the overrides are in.
+const SCENARIO_DIMENSION_KEY: &str = "grain.scenario_dimension";
+
+/// The schema meta-data key that holds the overrides.
+const OVERRIDES_KEY: &str = "grain.overrides";
+
+/// The schema meta-data key that holds the valid combinations.
+const VALID_COMBINATIONS_KEY: &str = "grain.valid_combinations";
+
+/// The schema meta-data key that holds the value of the cells of sparse
+/// data that are not stored.
+const FILL_KEY: &str = "grain.fill";
+
+/// The name of the column that holds the values.
+const VALUES_COLUMN: &str = "values";
+
+/// The name of the column that holds the offset of each stored cell of
+/// sparse data.
+const OFFSETS_COLUMN: &str = "offsets";
+
+impl Data {
+    /// Writes the data to `writer` in the Arrow IPC file format.
+    pub fn write_ipc<W: Write>(&self, writer: W) -> Result<()> {
+        let (base, overrides) = self.base_and_overrides();
+        let mut metadata = HashMap::from([
+            (GRANULARITY_KEY.to_string(), to_json(&base.granularity)?),
+            (ADDITIVITY_KEY.to_string(), to_json(&self.additivity)?),
+        ]);
+        if let Some(unit) = &self.unit {
+            metadata.insert(UNIT_KEY.to_string(), to_json(unit)?);
+        }
+        if let Some(dimension_name) = self.scenario_dimension() {
+            metadata.insert(
+                SCENARIO_DIMENSION_KEY.to_string(),
+                to_json(&dimension_name)?,
+            );
+            metadata.insert(OVERRIDES_KEY.to_string(), to_json(&overrides)?);
+        }
+        if self.has_valid_combinations() {
+            metadata.insert(
+                VALID_COMBINATIONS_KEY.to_string(),
+                to_json(&self.valid_combinations)?,
+            );
+        }
+        let values_field = Field::new(VALUES_COLUMN, DataType::Float64, false);
+        let (fields, columns): (Vec<Field>, Vec<ArrayRef>) = match base.stored() {
+            Stored::Dense(values) => (vec![values_field], vec![Arc::new(values.clone())]),
+            Stored::Sparse(sparse) => {
+                metadata.insert(FILL_KEY.to_string(), to_json(&sparse.fill)?);
+                let offsets = sparse.offsets.iter().map(|offset| *offset as u64);
+                (
+                    vec![
+                        Field::new(OFFSETS_COLUMN, DataType::UInt64, false),
+                        values_field,
+                    ],
+                    vec![
+                        Arc::new(UInt64Array::from_iter_values(offsets)),
+                        Arc::new(Float64Array::from(sparse.values.clone())),
+                    ],
+                )
+            }
+        };
+        let schema = Schema::new(fields).with_metadata(metadata);
+        let batch = RecordBatch::try_new(Arc::new(schema.clone()), columns)
+            .map_err(|e| Error::Io(e.to_string()))?;
+
+        let mut writer =
+            FileWriter::try_new(writer, &schema).map_err(|e| Error::Io(e.to_string()))?;
+        writer.write(&batch).map_err(|e| Error::Io(e.to_string()))?;
+        writer.finish().map_err(|e| Error::Io(e.to_string()))
+    }
+
+    /// Reads data previously written by `write_ipc` from `reader`.
+    ///
+    /// The values column of dense data is used directly, without being
+    /// copied.
+    pub fn read_ipc<R: Read + Seek>(reader: R) -> Result<Data> {
+        let mut reader = FileReader::try_new(reader, None).map_err(|e| Error::Io(e.to_string()))?;
+
+        let schema = reader.schema();
+        let granularity: Granularity = from_json(&schema, GRANULARITY_KEY)?.ok_or_else(|| {
+            Error::InvalidGranularity(format!("missing '{}' meta-data", GRANULARITY_KEY))
+        })?;
+
+        let batch = match (reader.next(), reader.next()) {
+            (Some(batch), None) => batch.map_err(|e| Error::Io(e.to_string()))?,
+            _ => return Err(Error::Io("expected a single record batch".to_string())),
+        };
+        let values = column(&batch, VALUES_COLUMN)?
+            .as_primitive_opt::<Float64Type>()
+            .ok_or_else(|| Error::Io(format!("'{}' column is not Float64", VALUES_COLUMN)))?
+            .clone();
+        if values.null_count() != 0 {
+            return Err(Error::Io(format!(
+                "'{}' column contains nulls",
+                VALUES_COLUMN
+            )));
+        }
+
+        let mut data = if batch.column_by_name(OFFSETS_COLUMN).is_some() {
+            let offsets = column(&batch, OFFSETS_COLUMN)?
+                .as_primitive_opt::<UInt64Type>()
+                .ok_or_else(|| Error::Io(format!("'{}' column is not UInt64", OFFSETS_COLUMN)))?;
+            if offsets.null_count() != 0 {
+                return Err(Error::Io(format!(
+                    "'{}' column contains nulls",
+                    OFFSETS_COLUMN
+                )));
+            }
+            // `NaN` is encoded as `null` in JSON.
+            let fill: Option<Option<f64>> = from_json(&schema, FILL_KEY)?;
+            let sparse = SparseValues::try_new(
+                offsets
+                    .values()
+                    .iter()
+                    .map(|offset| *offset as usize)
+                    .collect(),
+                values.values().to_vec(),
+                fill.flatten().unwrap_or(f64::NAN),
+                granularity.len(),
+            )?;
+            Data::from_sparse(granularity, sparse)
+        } else {
+            if values.len() != granularity.len() {
+                return Err(Error::LengthMismatch {
+                    expected: granularity.len(),
+                    actual: values.len(),
+                });
+            }
+            Data::from_parts(granularity, values)
+        };
+        data.additivity = from_json(&schema, ADDITIVITY_KEY)?.unwrap_or_default();
+        data.unit = from_json(&schema, UNIT_KEY)?;
+        let dimension_name: Option<String> = from_json(&schema, SCENARIO_DIMENSION_KEY)?;
+        let overrides: Option<Vec<Override>> = from_json(&schema, OVERRIDES_KEY)?;
+        let valid_combinations = from_json(&schema, VALID_COMBINATIONS_KEY)?;
+        data.with_overrides(dimension_name.as_deref(), &overrides.unwrap_or_default())?
+            .with_each_valid_combinations(valid_combinations.unwrap_or_default())
+    }
+}
+
+fn column<'a>(batch: &'a RecordBatch, name: &str) -> Result<&'a ArrayRef> {
+    batch
+        .column_by_name(name)
+        .ok_or_else(|| Error::Io(format!("missing '{}' column", name)))
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String> {
+    serde_json::to_string(value).map_err(|e| Error::Io(e.to_string()))
+}
+
+/// Decodes the schema meta-data under `key`, if there is any.
+fn from_json<T: DeserializeOwned>(schema: &Schema, key: &str) -> Result<Option<T>> {
+    schema
+        .metadata()
+        .get(key)
+        .map(|encoded| serde_json::from_str(encoded))
+        .transpose()
+        .map_err(|e| Error::InvalidGranularity(format!("invalid '{}' meta-data: {}", key, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{Additivity, Aggregation, Period, Unit, operators::mul};
+
+    #[test]
+    fn test_round_trip() {
+        let price = Data::new(
+            "time".to_string(),
+            vec![Period::monthly(2024, 1), Period::monthly(2024, 2)],
+            vec![1.0, 2.0],
+        );
+        let volume = Data::new("region".to_string(), vec!["EU", "US", "APAC"], vec![3.0; 3]);
+        let data = mul(&price, &volume)
+            .with_additivity("time", Additivity::SemiAdditive)
+            .with_unit(Unit::parse("USD/unit").unwrap());
+
+        let mut buffer = Vec::new();
+        data.write_ipc(&mut buffer).unwrap();
+        let decoded = Data::read_ipc(Cursor::new(buffer)).unwrap();
+
+        assert!(decoded.granularity() == data.granularity());
+        assert_eq!(decoded.values(), data.values());
+        assert_eq!(decoded.additivity("time"), Additivity::SemiAdditive);
+        assert_eq!(decoded.unit(), data.unit());
+    }
+
+    #[test]
+    fn test_overrides_round_trip() {
+        let data = Data::new("region".to_string(), vec!["EU", "US"], vec![1.0, 2.0])
+            .with_override_in("what_if", "downside", &[("region", "EU")], 0.5)
+            .unwrap();
+
+        let mut buffer = Vec::new();
+        data.write_ipc(&mut buffer).unwrap();
+        let decoded = Data::read_ipc(Cursor::new(buffer)).unwrap();
+
+        assert_eq!(decoded.stored_len(), 2);
+        assert!(decoded.granularity() == data.granularity());
+        assert_eq!(decoded.values(), data.values());
+        assert_eq!(decoded.scenarios(), vec!["base", "downside"]);
+    }
+
+    #[test]
+    fn test_sparse_round_trip() {
+        let data = Data::from_rows_sparse(&["region"], [(vec!["EU"], 1.0), (vec!["APAC"], 3.0)])
+            .unwrap()
+            .with_unit(Unit::parse("USD").unwrap());
+
+        let mut buffer = Vec::new();
+        data.write_ipc(&mut buffer).unwrap();
+        let decoded = Data::read_ipc(Cursor::new(buffer)).unwrap();
+
+        assert!(decoded.is_sparse());
+        assert_eq!(decoded.stored_len(), 2);
+        assert!(decoded.granularity() == data.granularity());
+        assert_eq!(decoded.unit(), data.unit());
+        let (stored, original) = (decoded.sparse().unwrap(), data.sparse().unwrap());
+        assert_eq!(stored.offsets, original.offsets);
+        assert_eq!(stored.values, original.values);
+        assert!(stored.fill.is_nan());
+    }
+
+    #[test]
+    fn test_valid_combinations_round_trip() {
+        let data = Data::from_rows(
+            &["product", "region"],
+            [
+                (vec!["A", "EU"], 1.0),
+                (vec!["A", "US"], 2.0),
+                (vec!["B", "EU"], 3.0),
+                (vec!["B", "US"], 4.0),
+            ],
+        )
+        .unwrap()
+        .with_valid_combinations(&["product", "region"], [vec!["A", "EU"], vec!["B", "US"]])
+        .unwrap();
+
+        let mut buffer = Vec::new();
+        data.write_ipc(&mut buffer).unwrap();
+        let decoded = Data::read_ipc(Cursor::new(buffer)).unwrap();
+
+        assert!(decoded.has_valid_combinations());
+        assert_eq!(decoded.stored_len(), 2);
+        let total = decoded.reduce("region", Aggregation::Sum).unwrap();
+        assert_eq!(total.values().values().to_vec(), vec![1.0, 4.0]);
+    }
+
+    #[test]
+    fn test_missing_granularity() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            VALUES_COLUMN,
+            DataType::Float64,
+            false,
+        )]));
+        let values: Arc<dyn Array> = Arc::new(Float64Array::from(vec![1.0]));
+        let batch = RecordBatch::try_new(schema.clone(), vec![values]).unwrap();
+
+        let mut buffer = Vec::new();
+        let mut writer = FileWriter::try_new(&mut buffer, &schema).unwrap();
+        writer.write(&batch).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+
+        let result = Data::read_ipc(Cursor::new(buffer));
+        assert!(matches!(result, Err(Error::InvalidGranularity(_))));
+    }
+}
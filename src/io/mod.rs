@@ -0,0 +1,8 @@
+//! Contains the readers and writers used to persist `Data`.
+
+#[cfg(feature = "csv")]
+mod csv;
+#[cfg(feature = "ipc")]
+mod ipc;
+#[cfg(feature = "parquet")]
+mod parquet;
@@ -0,0 +1,300 @@
+//! Contains reading and writing of `Data` as Parquet files in "long"
+//! format, i.e. one row per cell.
+//!
+//! Each dimension that the data varies by is written as a dictionary
+//! encoded column followed by a column holding the values.  Periods are
+//! written as strings and tagged in the field meta-data so they can be
+//! parsed back into periods.
+
+use std::{collections::HashMap, fs::File, path::Path, sync::Arc};
+
+use arrow_array::{
+    Array, ArrayRef, DictionaryArray, Int32Array, Int64Array, RecordBatch, StringArray,
+    cast::AsArray,
+    types::{Date32Type, Float64Type, Int32Type, Int64Type},
+};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::{ArrowWriter, arrow_reader::ParquetRecordBatchReaderBuilder};
+
+use crate::{
+    Data, DimensionValue, DimensionValues,
+    error::{Error, Result},
+    granularity::{Period, ValueKind},
+};
+
+/// The name of the column that `to_parquet` writes the values to.
+const VALUE_COLUMN: &str = "value";
+
+/// The field meta-data key that marks a column as holding periods.
+const KIND_KEY: &str = "grain.kind";
+
+/// The field meta-data key that holds the fiscal year start of a period column.
+const FISCAL_YEAR_START_KEY: &str = "grain.fiscal_year_start";
+
+impl Data {
+    /// Writes the data to a Parquet file at `path` in long format.
+    ///
+    /// The values are written to a column called `value`.
+    pub fn to_parquet(&self, path: impl AsRef<Path>) -> Result<()> {
+        let granularity = self.granularity();
+        let dimensions: Vec<_> = granularity.dimensions().collect();
+        let positions: Vec<usize> = dimensions
+            .iter()
+            .map(|(name, _)| granularity.dimension_index(name).unwrap())
+            .collect();
+
+        let mut keys = vec![Vec::with_capacity(granularity.len()); dimensions.len()];
+        granularity.for_each_cell(|index| {
+            for (keys, position) in keys.iter_mut().zip(&positions) {
+                keys.push(index[*position] as i32);
+            }
+        });
+
+        let mut fields = Vec::with_capacity(dimensions.len() + 1);
+        let mut columns: Vec<ArrayRef> = Vec::with_capacity(dimensions.len() + 1);
+        for ((name, values), keys) in dimensions.iter().zip(keys) {
+            let (dictionary, metadata) = encode_values(values);
+            let data_type = DataType::Dictionary(
+                Box::new(DataType::Int32),
+                Box::new(dictionary.data_type().clone()),
+            );
+            fields.push(Field::new(*name, data_type, false).with_metadata(metadata));
+            let column = DictionaryArray::<Int32Type>::try_new(Int32Array::from(keys), dictionary)
+                .map_err(|e| Error::Io(e.to_string()))?;
+            columns.push(Arc::new(column));
+        }
+        fields.push(Field::new(VALUE_COLUMN, DataType::Float64, false));
+        columns.push(Arc::new(self.values().clone()));
+
+        let schema = Arc::new(Schema::new(fields));
+        let batch =
+            RecordBatch::try_new(schema.clone(), columns).map_err(|e| Error::Io(e.to_string()))?;
+
+        let file = File::create(path).map_err(|e| Error::Io(e.to_string()))?;
+        let mut writer =
+            ArrowWriter::try_new(file, schema, None).map_err(|e| Error::Io(e.to_string()))?;
+        writer.write(&batch).map_err(|e| Error::Io(e.to_string()))?;
+        writer.close().map_err(|e| Error::Io(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Reads a Parquet file at `path` in long format, using the columns
+    /// `dimension_names` as dimensions and `value_column` as the values.
+    ///
+    /// Dimension columns can hold strings, integers or dates, optionally
+    /// dictionary encoded.  Null values are read as `NaN`.
+    pub fn from_parquet(
+        path: impl AsRef<Path>,
+        dimension_names: &[&str],
+        value_column: &str,
+    ) -> Result<Data> {
+        let file = File::open(path).map_err(|e| Error::Io(e.to_string()))?;
+        let builder =
+            ParquetRecordBatchReaderBuilder::try_new(file).map_err(|e| Error::Io(e.to_string()))?;
+        let schema = builder.schema().clone();
+        let reader = builder.build().map_err(|e| Error::Io(e.to_string()))?;
+
+        let column_index = |name: &str| {
+            schema
+                .index_of(name)
+                .map_err(|_| Error::Io(format!("missing column '{}'", name)))
+        };
+        let dimension_columns = dimension_names
+            .iter()
+            .map(|name| column_index(name))
+            .collect::<Result<Vec<_>>>()?;
+        let value_index = column_index(value_column)?;
+
+        let mut rows = Vec::new();
+        for batch in reader {
+            let batch = batch.map_err(|e| Error::Io(e.to_string()))?;
+            let coordinates = dimension_columns
+                .iter()
+                .map(|idx| decode_values(batch.column(*idx).as_ref(), schema.field(*idx)))
+                .collect::<Result<Vec<_>>>()?;
+            let values = batch
+                .column(value_index)
+                .as_primitive_opt::<Float64Type>()
+                .ok_or_else(|| Error::Io(format!("column '{}' is not Float64", value_column)))?;
+
+            for row in 0..batch.num_rows() {
+                let coordinate = coordinates.iter().map(|c| c[row].clone()).collect();
+                let value = if values.is_null(row) {
+                    f64::NAN
+                } else {
+                    values.value(row)
+                };
+                rows.push((coordinate, value));
+            }
+        }
+
+        Data::from_rows::<DimensionValue>(dimension_names, rows)
+    }
+}
+
+/// Encodes the values of a dimension as the values of a dictionary, along
+/// with the field meta-data needed to decode them.
+fn encode_values(values: &DimensionValues) -> (ArrayRef, HashMap<String, String>) {
+    let mut metadata = HashMap::new();
+    let array: ArrayRef = match values.get(0).map(DimensionValue::kind) {
+        Some(ValueKind::Integer) => Arc::new(Int64Array::from_iter_values(
+            values.iter().map(|v| v.as_integer().unwrap()),
+        )),
+        Some(ValueKind::Date) => Arc::new(arrow_array::Date32Array::from_iter_values(
+            values
+                .iter()
+                .map(|v| Date32Type::from_naive_date(v.as_date().unwrap())),
+        )),
+        Some(ValueKind::Period) => {
+            let first = values.get(0).unwrap().as_period().unwrap();
+            metadata.insert(KIND_KEY.to_string(), "period".to_string());
+            metadata.insert(
+                FISCAL_YEAR_START_KEY.to_string(),
+                first.fiscal_year_start().to_string(),
+            );
+            Arc::new(StringArray::from_iter_values(
+                values.iter().map(|v| v.to_string()),
+            ))
+        }
+        Some(ValueKind::String) | None => Arc::new(StringArray::from_iter_values(
+            values.iter().map(|v| v.to_string()),
+        )),
+    };
+    (array, metadata)
+}
+
+/// Decodes a column into a dimension value per row.
+fn decode_values(array: &dyn Array, field: &Field) -> Result<Vec<DimensionValue>> {
+    if array.null_count() != 0 {
+        return Err(Error::Io(format!(
+            "dimension column '{}' contains nulls",
+            field.name()
+        )));
+    }
+
+    if let Some(dictionary) = array.as_any_dictionary_opt() {
+        let values = decode_values(dictionary.values().as_ref(), field)?;
+        return Ok(dictionary
+            .normalized_keys()
+            .into_iter()
+            .map(|key| values[key].clone())
+            .collect());
+    }
+
+    let strings: Vec<&str> = match array.data_type() {
+        DataType::Utf8 => array.as_string::<i32>().iter().flatten().collect(),
+        DataType::LargeUtf8 => array.as_string::<i64>().iter().flatten().collect(),
+        DataType::Utf8View => array.as_string_view().iter().flatten().collect(),
+        DataType::Int32 => {
+            let array = array.as_primitive::<Int32Type>();
+            return Ok(array.values().iter().map(|v| (*v).into()).collect());
+        }
+        DataType::Int64 => {
+            let array = array.as_primitive::<Int64Type>();
+            return Ok(array.values().iter().map(|v| (*v).into()).collect());
+        }
+        DataType::Date32 => {
+            let array = array.as_primitive::<Date32Type>();
+            return Ok(array
+                .values()
+                .iter()
+                .map(|v| Date32Type::to_naive_date(*v).into())
+                .collect());
+        }
+        other => {
+            return Err(Error::Io(format!(
+                "dimension column '{}' has unsupported type {}",
+                field.name(),
+                other
+            )));
+        }
+    };
+
+    let metadata = field.metadata();
+    if metadata.get(KIND_KEY).map(String::as_str) == Some("period") {
+        let fiscal_year_start = metadata
+            .get(FISCAL_YEAR_START_KEY)
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(1);
+        strings
+            .into_iter()
+            .map(|s| Period::parse(s, fiscal_year_start).map(DimensionValue::from))
+            .collect()
+    } else {
+        Ok(strings.into_iter().map(DimensionValue::from).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::operators::mul;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("grain-{}-{}.parquet", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let price = Data::new(
+            "time".to_string(),
+            vec![Period::fiscal(crate::Frequency::Quarterly, 2024, 1, 7)],
+            vec![2.0],
+        );
+        let volume = Data::new(
+            "region".to_string(),
+            vec!["EU", "US", "APAC"],
+            vec![1.0, 2.0, 3.0],
+        );
+        let store = Data::new("store".to_string(), vec![10, 20], vec![1.0, 10.0]);
+        let data = mul(&mul(&price, &volume), &store);
+
+        let path = temp_path("round-trip");
+        data.to_parquet(&path).unwrap();
+        let decoded = Data::from_parquet(&path, &["time", "region", "store"], "value").unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(decoded.granularity() == data.granularity());
+        assert_eq!(decoded.values(), data.values());
+    }
+
+    #[test]
+    fn test_plain_columns() {
+        let date = |d| NaiveDate::from_ymd_opt(2024, 1, d).unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("day", DataType::Date32, false),
+            Field::new("amount", DataType::Float64, true),
+        ]));
+        let days = arrow_array::Date32Array::from_iter_values(
+            [1, 2].map(|d| Date32Type::from_naive_date(date(d))),
+        );
+        let amounts = arrow_array::Float64Array::from(vec![Some(1.5), None]);
+        let batch =
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(days), Arc::new(amounts)]).unwrap();
+
+        let path = temp_path("plain");
+        let mut writer = ArrowWriter::try_new(File::create(&path).unwrap(), schema, None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+        let data = Data::from_parquet(&path, &["day"], "amount").unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let days = data.granularity().dimension_values("day").unwrap();
+        assert_eq!(days.get(1), Some(&date(2).into()));
+        assert_eq!(data.values().value(0), 1.5);
+        assert!(data.values().value(1).is_nan());
+    }
+
+    #[test]
+    fn test_missing_column() {
+        let data = Data::new("region".to_string(), vec!["EU"], vec![1.0]);
+        let path = temp_path("missing");
+        data.to_parquet(&path).unwrap();
+        let result = Data::from_parquet(&path, &["product"], "value");
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(Error::Io(reason)) if reason.contains("'product'")));
+    }
+}
@@ -1,6 +1,38 @@
+mod aggregation;
+mod cells;
+mod combinations;
+mod concat;
+mod currency;
 mod data;
+mod display;
+mod error;
+mod expr;
+mod formula;
 mod granularity;
-mod operators;
+mod io;
+mod lookup;
+mod model;
+pub mod operators;
 mod query;
+mod reduce;
+mod resample;
+mod scenario;
+#[cfg(feature = "serde")]
+mod serialization;
+mod sparse;
+mod unit;
+mod view;
 
+pub use aggregation::{Additivity, Aggregation};
+pub use cells::{Cells, CellsIterMut, CellsMut, Coordinate};
+pub use currency::CurrencyConversion;
 pub use data::*;
+pub use display::{Pivot, PivotOptions};
+pub use error::{Error, Result};
+pub use expr::Expr;
+pub use formula::{Broadcast, Formula, Inference};
+pub use granularity::{DimensionValue, DimensionValues, Frequency, Granularity, Period, ValueKind};
+pub use model::Model;
+pub use resample::ResampleTo;
+pub use unit::{Unit, UnitConversions};
+pub use view::DataView;
@@ -0,0 +1,287 @@
+//! Contains the lookup of `Data` using another dimension as the key.
+//!
+//! A key assigns a value of the dimension being looked up to each of its
+//! cells, e.g. each store its price tier, which picks that tier's rate for
+//! the store.  The key is itself `Data` that varies by the dimension, with a
+//! non-zero value at the value assigned to each cell.  Keys are resolved
+//! against the values of the dimension, so they do not depend on its layout.
+
+use crate::{
+    Data, DimensionValue, Granularity,
+    data::Values,
+    error::{Error, Result},
+    unit::Unit,
+};
+
+impl Data {
+    /// Picks the value of `self` at the value of the dimension
+    /// `dimension_name` that `key` assigns to each of its cells, e.g. the
+    /// rate of each store's price tier.
+    ///
+    /// `key` varies by `dimension_name` and has a non-zero value at the value
+    /// assigned to each cell of its other dimensions, e.g. `1.0` at
+    /// `{store: Paris, tier: Gold}`, see `lookup_rows`.  The result varies by
+    /// the other dimensions of `key` along with every other dimension of
+    /// `self`, and respects the valid combinations of both.  Cells that the
+    /// key assigns no value are `NaN`.
+    ///
+    /// Returns an error if a value of the dimension in `key` is not a value
+    /// in `self`, a cell is assigned more than one value or `key` has a unit
+    /// other than dimensionless.
+    pub fn lookup(&self, dimension_name: &str, key: &Data) -> Result<Data> {
+        if !self.granularity.maybe_varies_by(dimension_name) {
+            return Err(Error::UnknownDimension(dimension_name.to_string()));
+        }
+        if !key.granularity.maybe_varies_by(dimension_name) {
+            return Err(Error::InvalidGranularity(format!(
+                "the key must vary by '{}', the dimension being looked up",
+                dimension_name
+            )));
+        }
+        if let Some(unit) = key.unit()
+            && !unit.is_dimensionless()
+        {
+            return Err(Error::UnitMismatch {
+                lhs: unit.to_string(),
+                rhs: Unit::dimensionless().to_string(),
+            });
+        }
+        let values = self
+            .granularity
+            .dimension_values(dimension_name)
+            .expect("The dimension was checked");
+        let positions = key
+            .granularity
+            .dimension_values(dimension_name)
+            .expect("The dimension was checked")
+            .iter()
+            .map(|value| {
+                values.position(value).ok_or_else(|| Error::InvalidKey {
+                    dimension: dimension_name.to_string(),
+                    key: value.to_string(),
+                })
+            })
+            .collect::<Result<Vec<usize>>>()?;
+
+        // The position in `self` assigned to each cell of the key.
+        let mut dimensions = key.granularity.dimensions_with_flags();
+        dimensions.retain(|(name, _, _)| name != dimension_name);
+        let granularity = Granularity::from_dimensions(dimensions);
+        let run_lengths = key.granularity.run_lengths_for(&granularity);
+        let run_length = *key.granularity.run_length(dimension_name);
+        let source = key.values().values();
+        let mut assigned = Vec::with_capacity(granularity.len());
+        let mut ambiguous = false;
+        granularity.for_each_cell(|index| {
+            let offset: usize = index.iter().zip(&run_lengths).map(|(i, r)| i * r).sum();
+            let mut picked = positions
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| {
+                    let value = source[offset + i * run_length];
+                    value != 0.0 && !value.is_nan()
+                })
+                .map(|(_, position)| *position as f64);
+            assigned.push(picked.next().unwrap_or(f64::NAN));
+            ambiguous |= picked.next().is_some();
+        });
+        if ambiguous {
+            return Err(Error::InvalidGranularity(format!(
+                "the key assigns more than one value of '{}' to a cell",
+                dimension_name
+            )));
+        }
+
+        let mut positions = Data::from_parts(granularity, Values::from(assigned));
+        positions.valid_combinations = key.valid_combinations.clone();
+        let positions = positions.forget_valid_combinations_of(dimension_name);
+        self.lookup_positions(dimension_name, &positions)
+    }
+
+    /// Like `lookup` but with the key given as rows in "long" format, see
+    /// `from_rows`, of the values of `key_dimensions` and the value of
+    /// `dimension_name` they are assigned, e.g.
+    /// `rates.lookup_rows("tier", &["store"], [(vec!["Paris"], "Gold")])`.
+    ///
+    /// Returns an error as `lookup` does, or if the key dimensions include
+    /// the dimension being looked up.
+    pub fn lookup_rows<K, V>(
+        &self,
+        dimension_name: &str,
+        key_dimensions: &[&str],
+        key: impl IntoIterator<Item = (Vec<K>, V)>,
+    ) -> Result<Data>
+    where
+        K: Into<DimensionValue>,
+        V: Into<DimensionValue>,
+    {
+        let mut dimension_names = key_dimensions.to_vec();
+        dimension_names.push(dimension_name);
+        let rows = key.into_iter().map(|(coordinate, value)| {
+            let mut coordinate: Vec<DimensionValue> =
+                coordinate.into_iter().map(Into::into).collect();
+            coordinate.push(value.into());
+            (coordinate, 1.0)
+        });
+        self.lookup(dimension_name, &Data::from_rows(&dimension_names, rows)?)
+    }
+
+    /// Picks the value at the position given by `key` along the dimension
+    /// `dimension_name`, where a `NaN` position is `NaN`.
+    fn lookup_positions(&self, dimension_name: &str, key: &Data) -> Result<Data> {
+        let mut remaining = self.granularity.clone();
+        remaining.drop(dimension_name);
+        let granularity = remaining.try_broadcast(&key.granularity)?;
+        let run_lengths = self.granularity.run_lengths_for(&granularity);
+        let run_length = *self.granularity.run_length(dimension_name);
+        let key_run_lengths = key.granularity.run_lengths_for(&granularity);
+        let offset = |index: &[usize], run_lengths: &[usize]| -> usize {
+            index.iter().zip(run_lengths).map(|(i, r)| i * r).sum()
+        };
+
+        let (source, keys) = (self.values().values(), key.values().values());
+        let mut values = Vec::with_capacity(granularity.len());
+        granularity.for_each_cell(|index| {
+            let position = keys[offset(index, &key_run_lengths)];
+            if position.is_nan() {
+                values.push(f64::NAN);
+            } else {
+                let position = position as usize;
+                values.push(source[offset(index, &run_lengths) + position * run_length]);
+            }
+        });
+
+        let mut data = Data::from_parts(granularity, Values::from(values)).with_metadata_from(self);
+        data.additivity.remove(dimension_name);
+        Ok(data
+            .forget_valid_combinations_of(dimension_name)
+            .restricted_by(&[&key.valid_combinations]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let rates = Data::from_rows(
+            &["tier", "region"],
+            [
+                (vec!["Bronze", "EU"], 1.0),
+                (vec!["Silver", "EU"], 2.0),
+                (vec!["Gold", "EU"], 3.0),
+                (vec!["Bronze", "US"], 10.0),
+                (vec!["Silver", "US"], 20.0),
+                (vec!["Gold", "US"], 30.0),
+            ],
+        )
+        .unwrap();
+        // Only the assigned tiers appear in the key, in their own order.
+        let key = Data::from_rows(
+            &["store", "tier"],
+            [
+                (vec!["Paris", "Gold"], 1.0),
+                (vec!["Berlin", "Bronze"], 1.0),
+                (vec!["Austin", "Silver"], 1.0),
+            ],
+        )
+        .unwrap();
+
+        let result = rates.lookup("tier", &key).unwrap();
+        assert!(!result.granularity().maybe_varies_by("tier"));
+        assert_eq!(result.granularity().shape(), vec![2, 3]);
+        assert_eq!(
+            result.values().values().to_vec(),
+            vec![3.0, 1.0, 2.0, 30.0, 10.0, 20.0]
+        );
+
+        // Cells missing from the key are `NaN`.
+        let assigned = [
+            (vec!["Paris", "online"], "Gold"),
+            (vec!["Paris", "retail"], "Silver"),
+            (vec!["Berlin", "online"], "Bronze"),
+        ];
+        let result = rates
+            .lookup_rows("tier", &["store", "channel"], assigned)
+            .unwrap();
+        let values = result.values().values();
+        assert_eq!(values[..5], [3.0, 1.0, 30.0, 10.0, 2.0]);
+        assert!(values[5].is_nan() && values[7].is_nan());
+    }
+
+    #[test]
+    fn test_lookup_valid_combinations() {
+        let rates = Data::from_rows(
+            &["tier", "region"],
+            [
+                (vec!["Bronze", "EU"], 1.0),
+                (vec!["Gold", "EU"], 3.0),
+                (vec!["Bronze", "US"], 10.0),
+                (vec!["Gold", "US"], 30.0),
+            ],
+        )
+        .unwrap();
+        // Berlin is only open in the EU.
+        let key = Data::from_rows(
+            &["store", "tier"],
+            [
+                (vec!["Paris", "Gold"], 1.0),
+                (vec!["Berlin", "Bronze"], 1.0),
+            ],
+        )
+        .unwrap()
+        .with_valid_combinations(
+            &["store", "region"],
+            [
+                vec!["Paris", "EU"],
+                vec!["Paris", "US"],
+                vec!["Berlin", "EU"],
+            ],
+        )
+        .unwrap();
+
+        let result = rates.lookup("tier", &key).unwrap();
+        assert!(result.has_valid_combinations());
+        assert_eq!(result.stored_len(), 3);
+        let values = result.values().values();
+        assert_eq!(values[..3], [3.0, 1.0, 30.0]);
+        assert!(values[3].is_nan());
+    }
+
+    #[test]
+    fn test_invalid_key() {
+        let rates = Data::new(
+            "tier".to_string(),
+            vec!["Bronze", "Silver", "Gold"],
+            vec![1.0, 2.0, 3.0],
+        );
+        let error = rates
+            .lookup_rows("tier", &["store"], [(vec!["Paris"], "Platinum")])
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Key 'Platinum' is not a value of 'tier'");
+        assert!(matches!(
+            rates.lookup_rows("store", &["city"], [(vec!["Paris"], "Gold")]),
+            Err(Error::UnknownDimension(_))
+        ));
+        assert!(matches!(
+            rates.lookup_rows("tier", &["tier"], [(vec!["Gold"], "Gold")]),
+            Err(Error::InvalidGranularity(_))
+        ));
+
+        let key = Data::from_rows(
+            &["store", "tier"],
+            [(vec!["Paris", "Gold"], 1.0), (vec!["Paris", "Silver"], 1.0)],
+        )
+        .unwrap();
+        assert!(matches!(
+            rates.lookup("tier", &key),
+            Err(Error::InvalidGranularity(_))
+        ));
+        let key = key.with_unit(Unit::base("USD"));
+        assert!(matches!(
+            rates.lookup("tier", &key),
+            Err(Error::UnitMismatch { .. })
+        ));
+    }
+}
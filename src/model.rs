@@ -0,0 +1,390 @@
+//! Contains the `Model` type, a collection of named inputs and formulas.
+//!
+//! The formulas form a dependency graph over the variables they refer to.
+//! Results are cached and only the formulas that depend, directly or
+//! indirectly, on a changed input or formula are calculated again.
+
+use std::collections::{HashMap, HashSet};
+
+use indexmap::IndexMap;
+
+use crate::{
+    Data, Granularity,
+    error::{Error, Result},
+    formula::{Formula, Inference, parser},
+    operators::BroadcastPolicy,
+};
+
+/// A collection of named `Data` inputs and named formulas defined over them.
+#[derive(Debug, Clone, Default)]
+pub struct Model {
+    inputs: IndexMap<String, Data>,
+    formulas: IndexMap<String, Formula>,
+
+    /// The policy that every operation in a formula must follow.
+    policy: BroadcastPolicy,
+
+    /// The result of each formula that is up to date.
+    results: HashMap<String, Data>,
+}
+
+impl Model {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the policy that every operation in a formula must follow, which
+    /// means every formula is calculated again.
+    pub fn set_broadcast_policy(&mut self, policy: BroadcastPolicy) {
+        self.policy = policy;
+        self.results.clear();
+    }
+
+    /// Sets the input `name` to `data`, replacing any input or formula
+    /// with the same name.
+    pub fn set_input(&mut self, name: &str, data: Data) {
+        self.formulas.shift_remove(name);
+        self.inputs.insert(name.to_string(), data);
+        self.invalidate(name);
+    }
+
+    /// Defines the formula `name`, replacing any input or formula with the
+    /// same name.
+    ///
+    /// Variables that are not defined yet are allowed, but the formula is
+    /// rejected if it would create a circular reference.
+    pub fn define(&mut self, name: &str, formula: Formula) -> Result<()> {
+        let input = self.inputs.shift_remove(name);
+        let previous = self.formulas.insert(name.to_string(), formula);
+
+        if let Err(error) = self.calculation_order([name]) {
+            match previous {
+                Some(previous) => self.formulas.insert(name.to_string(), previous),
+                None => self.formulas.shift_remove(name),
+            };
+            if let Some(input) = input {
+                self.inputs.insert(name.to_string(), input);
+            }
+            return Err(error);
+        }
+        self.invalidate(name);
+        Ok(())
+    }
+
+    /// Parses and defines each formula in `source`, e.g. the contents of a
+    /// configuration file, returning their names.
+    ///
+    /// See `Formula::parse` for the syntax, definitions are separated by
+    /// new lines or `;`.  Nothing is defined if any formula is invalid.
+    pub fn define_source(&mut self, source: &str) -> Result<Vec<String>> {
+        let definitions = parser::parse_definitions(source)?;
+        let mut model = self.clone();
+        for (name, formula) in &definitions {
+            model.define(name, formula.clone())?;
+        }
+        *self = model;
+        Ok(definitions.into_iter().map(|(name, _)| name).collect())
+    }
+
+    pub fn input(&self, name: &str) -> Option<&Data> {
+        self.inputs.get(name)
+    }
+
+    pub fn formula(&self, name: &str) -> Option<&Formula> {
+        self.formulas.get(name)
+    }
+
+    /// Returns the value of the input or formula `name`, calculating it and
+    /// anything it depends on as required.
+    pub fn get(&mut self, name: &str) -> Result<&Data> {
+        if !self.inputs.contains_key(name) {
+            if !self.formulas.contains_key(name) {
+                return Err(Error::UnknownVariable(name.to_string()));
+            }
+            self.calculate([name])?;
+        }
+        Ok(self
+            .inputs
+            .get(name)
+            .or_else(|| self.results.get(name))
+            .unwrap())
+    }
+
+    /// Infers the granularity of every formula from the granularity of the
+    /// inputs, without calculating any values, returning them in calculation
+    /// order.
+    ///
+    /// This catches conflicting dimension values and unknown variables up
+    /// front, and the broadcasts of each `Inference` show where the model
+    /// would grow.
+    pub fn check(&self) -> Result<Vec<(String, Inference)>> {
+        let order = self.calculation_order(self.formulas.keys().map(String::as_str))?;
+        let mut granularities: HashMap<&str, Granularity> = HashMap::new();
+        let mut inferences = Vec::with_capacity(order.len());
+        for name in order {
+            let lookup = |variable: &str| {
+                self.inputs
+                    .get(variable)
+                    .map(Data::granularity)
+                    .or_else(|| granularities.get(variable))
+                    .ok_or_else(|| Error::UnknownVariable(variable.to_string()))
+            };
+            let inference = self.formulas[name]
+                .infer_with_policy(&lookup, &self.policy)
+                .map_err(|error| Error::Formula {
+                    name: name.to_string(),
+                    error: Box::new(error),
+                })?;
+            granularities.insert(name, inference.granularity.clone());
+            inferences.push((name.to_string(), inference));
+        }
+        Ok(inferences)
+    }
+
+    /// Calculates every formula that is not up to date, returning their names
+    /// in the order they were calculated.
+    pub fn recalculate(&mut self) -> Result<Vec<String>> {
+        let names: Vec<String> = self.formulas.keys().cloned().collect();
+        self.calculate(names.iter().map(String::as_str))
+    }
+
+    /// Calculates the formulas `names`, and any they depend on, that are not
+    /// up to date.
+    fn calculate<'n>(&mut self, names: impl IntoIterator<Item = &'n str>) -> Result<Vec<String>> {
+        let order: Vec<String> = self
+            .calculation_order(names)?
+            .into_iter()
+            .filter(|name| !self.results.contains_key(*name))
+            .map(str::to_string)
+            .collect();
+
+        for name in &order {
+            let lookup = |variable: &str| {
+                self.inputs
+                    .get(variable)
+                    .or_else(|| self.results.get(variable))
+                    .ok_or_else(|| Error::UnknownVariable(variable.to_string()))
+            };
+            let data = self.formulas[name]
+                .to_expr(&lookup)?
+                .evaluate_with_policy(&self.policy)?;
+            self.results.insert(name.clone(), data);
+        }
+        Ok(order)
+    }
+
+    /// Returns the formulas needed to calculate `names` such that each comes
+    /// after the formulas it depends on.
+    ///
+    /// Returns an error if there is a circular reference.
+    fn calculation_order<'n>(&self, names: impl IntoIterator<Item = &'n str>) -> Result<Vec<&str>> {
+        let mut order = Vec::new();
+        let mut done = HashSet::new();
+        let mut path = Vec::new();
+        for name in names {
+            if let Some((name, _)) = self.formulas.get_key_value(name) {
+                self.visit(name, &mut path, &mut done, &mut order)?;
+            }
+        }
+        Ok(order)
+    }
+
+    fn visit<'a>(
+        &'a self,
+        name: &'a str,
+        path: &mut Vec<&'a str>,
+        done: &mut HashSet<&'a str>,
+        order: &mut Vec<&'a str>,
+    ) -> Result<()> {
+        if done.contains(name) {
+            return Ok(());
+        }
+        // Inputs and unknown variables have no dependencies.
+        let Some(formula) = self.formulas.get(name) else {
+            return Ok(());
+        };
+        if let Some(start) = path.iter().position(|n| *n == name) {
+            let mut cycle: Vec<String> = path[start..].iter().map(|n| n.to_string()).collect();
+            cycle.push(name.to_string());
+            return Err(Error::CircularReference(cycle));
+        }
+
+        path.push(name);
+        for variable in formula.variables() {
+            self.visit(variable, path, done, order)?;
+        }
+        path.pop();
+        done.insert(name);
+        order.push(name);
+        Ok(())
+    }
+
+    /// Discards the results of `name` and every formula that depends on it.
+    fn invalidate(&mut self, name: &str) {
+        self.results.remove(name);
+        let mut stale = vec![name.to_string()];
+        while let Some(name) = stale.pop() {
+            for (dependent, formula) in &self.formulas {
+                if formula.variables().contains(&name.as_str())
+                    && self.results.remove(dependent).is_some()
+                {
+                    stale.push(dependent.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> Model {
+        let mut model = Model::new();
+        model.set_input(
+            "price",
+            Data::new("product".to_string(), vec!["A", "B"], vec![1.0, 2.0]),
+        );
+        model.set_input(
+            "volume",
+            Data::new("region".to_string(), vec!["EU", "US"], vec![10.0, 100.0]),
+        );
+        model.set_input(
+            "discount",
+            Data::new("product".to_string(), vec!["A", "B"], vec![0.0, 5.0]),
+        );
+        model
+            .define("revenue", Formula::var("price") * "volume")
+            .unwrap();
+        model
+            .define("net", Formula::var("revenue") - "discount")
+            .unwrap();
+        model
+            .define("volume_growth", Formula::var("volume") * 1.1)
+            .unwrap();
+        model
+    }
+
+    #[test]
+    fn test_get() {
+        let mut model = model();
+        let net = model.get("net").unwrap();
+        assert_eq!(
+            net.values().values().to_vec(),
+            vec![10.0, 100.0, 15.0, 195.0]
+        );
+    }
+
+    #[test]
+    fn test_incremental_recalculation() {
+        let mut model = model();
+        assert_eq!(
+            model.recalculate().unwrap(),
+            vec!["revenue", "net", "volume_growth"]
+        );
+        assert!(model.recalculate().unwrap().is_empty());
+
+        model.set_input(
+            "discount",
+            Data::new("product".to_string(), vec!["A", "B"], vec![1.0, 1.0]),
+        );
+        assert_eq!(model.recalculate().unwrap(), vec!["net"]);
+
+        model.set_input(
+            "price",
+            Data::new("product".to_string(), vec!["A", "B"], vec![3.0, 4.0]),
+        );
+        assert_eq!(model.recalculate().unwrap(), vec!["revenue", "net"]);
+        let net = model.get("net").unwrap();
+        assert_eq!(
+            net.values().values().to_vec(),
+            vec![29.0, 299.0, 39.0, 399.0]
+        );
+    }
+
+    #[test]
+    fn test_circular_reference() {
+        let mut model = model();
+        let result = model.define("price", Formula::var("net") / 2.0);
+        assert_eq!(
+            result,
+            Err(Error::CircularReference(vec![
+                "price".to_string(),
+                "net".to_string(),
+                "revenue".to_string(),
+                "price".to_string()
+            ]))
+        );
+
+        // The model is left unchanged.
+        assert!(model.input("price").is_some());
+        assert!(model.get("net").is_ok());
+    }
+
+    #[test]
+    fn test_define_source() {
+        let mut model = model();
+        let names = model
+            .define_source("share = net / total; total = 1000\nhigh = if(share > 0.1, 1, 0)")
+            .unwrap();
+        assert_eq!(names, vec!["share", "total", "high"]);
+        let high = model.get("high").unwrap();
+        assert_eq!(high.values().values().to_vec(), vec![0.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_check() {
+        let mut model = model();
+        let checked = model.check().unwrap();
+        let names: Vec<_> = checked.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["revenue", "net", "volume_growth"]);
+        assert_eq!(checked[1].1.granularity.shape(), vec![2, 2]);
+        assert_eq!(
+            checked[1].1.broadcasts[0].to_string(),
+            "`revenue - discount` would broadcast `discount` across 2 values of 'region'"
+        );
+
+        model.set_input(
+            "discount",
+            Data::new("product".to_string(), vec!["A"], vec![0.0]),
+        );
+        assert_eq!(
+            model.check().unwrap_err().to_string(),
+            "In formula 'net': Dimension 'product' has conflicting values."
+        );
+    }
+
+    #[test]
+    fn test_broadcast_policy() {
+        let mut model = model();
+        let policy = BroadcastPolicy::default()
+            .tagged_only()
+            .broadcastable("product")
+            .broadcastable("region");
+        model.set_broadcast_policy(policy.clone());
+        assert!(model.check().is_ok());
+
+        model.set_broadcast_policy(policy.max_cells(3));
+        let expected = Error::Formula {
+            name: "revenue".to_string(),
+            error: Box::new(Error::TooManyCells { cells: 4, limit: 3 }),
+        };
+        assert_eq!(model.check().err(), Some(expected));
+        assert_eq!(
+            model.get("net").err(),
+            Some(Error::TooManyCells { cells: 4, limit: 3 })
+        );
+    }
+
+    #[test]
+    fn test_unknown_variable() {
+        let mut model = model();
+        model
+            .define("cost", Formula::var("unit_cost") * "volume")
+            .unwrap();
+        assert_eq!(
+            model.get("cost").err(),
+            Some(Error::UnknownVariable("unit_cost".to_string()))
+        );
+    }
+}
@@ -1,11 +1,130 @@
 mod mul;
+mod policy;
+
+use std::fmt;
 
-pub use mul::*;
 use arrow_array::Array;
+pub use mul::*;
+pub use policy::BroadcastPolicy;
 
-use crate::data::Values;
+use crate::{
+    Data, DataView, Granularity,
+    aggregation::combine_additivity,
+    combinations::{combine_valid_combinations, validity},
+    data::Values,
+    error::Result,
+    scenario::align_scenarios,
+    sparse::{SparseValues, sparse_binary_op},
+    unit::combine_units,
+};
 use arrow_buffer::Buffer;
 
+/// The element-wise binary operations that can be applied to `Data`.
+///
+/// Comparisons result in `1.0` when true and `0.0` otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl BinaryOp {
+    /// Applies the operation to a single pair of values.
+    #[inline]
+    pub fn apply(self, lhs: f64, rhs: f64) -> f64 {
+        let truth = |b: bool| if b { 1.0 } else { 0.0 };
+        match self {
+            BinaryOp::Add => lhs + rhs,
+            BinaryOp::Sub => lhs - rhs,
+            BinaryOp::Mul => lhs * rhs,
+            BinaryOp::Div => lhs / rhs,
+            BinaryOp::Lt => truth(lhs < rhs),
+            BinaryOp::Le => truth(lhs <= rhs),
+            BinaryOp::Gt => truth(lhs > rhs),
+            BinaryOp::Ge => truth(lhs >= rhs),
+            BinaryOp::Eq => truth(lhs == rhs),
+            BinaryOp::Ne => truth(lhs != rhs),
+        }
+    }
+
+    /// Returns the symbol used for the operation in formulas.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+        }
+    }
+
+    /// Returns whether the operation is a comparison.
+    pub fn is_comparison(self) -> bool {
+        !matches!(
+            self,
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div
+        )
+    }
+
+    /// Returns how tightly the operation binds, higher binds tighter.
+    pub(crate) fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Mul | BinaryOp::Div => 3,
+            BinaryOp::Add | BinaryOp::Sub => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+/// An operand of the operators, either a piece of `Data` or a `DataView` of
+/// one, so a slice can be used without copying it.
+pub trait Operand {
+    /// Returns a view of the whole operand.
+    fn view(&self) -> DataView<'_>;
+
+    /// Returns the operand as `Data` if it is not a slice.
+    fn as_data(&self) -> Option<&Data>;
+}
+
+impl Operand for Data {
+    fn view(&self) -> DataView<'_> {
+        Data::view(self)
+    }
+
+    fn as_data(&self) -> Option<&Data> {
+        Some(self)
+    }
+}
+
+impl Operand for DataView<'_> {
+    fn view(&self) -> DataView<'_> {
+        self.clone()
+    }
+
+    fn as_data(&self) -> Option<&Data> {
+        DataView::as_data(self)
+    }
+}
+
 /// Performs a scalar binary operation on `values`.
 ///
 /// Note, this function assumes that `values` does not have an allocated bitmap.
@@ -23,7 +142,6 @@ where
 /// Performs binary operation between two `Values`.
 ///
 /// Note, this function assumes that both `Values`'s are the same size and neither has an allocated bitmap.
-
 fn array_binary_op<F>(lhs: &Values, rhs: &Values, op: F) -> Values
 where
     F: Fn(f64, f64) -> f64,
@@ -45,3 +163,105 @@ where
     };
     Values::new(buffer.into(), None)
 }
+
+/// Performs a binary operation between two views, broadcasting each
+/// operand to `granularity`, the combined granularity of both as returned
+/// by `Granularity::try_broadcast`.
+///
+/// The values are read directly from the parent of each view using its
+/// strides so neither is expanded into an intermediate array.  Only the
+/// cells of the valid combinations of both operands are computed, and
+/// stored sparsely, if either declares any.
+fn broadcast_binary_op<F>(lhs: &DataView, rhs: &DataView, granularity: Granularity, op: F) -> Data
+where
+    F: Fn(f64, f64) -> f64,
+{
+    let lhs_strides = lhs.strides_for(&granularity);
+    let rhs_strides = rhs.strides_for(&granularity);
+    let offset = |index: &[usize], strides: &[usize]| -> usize {
+        index.iter().zip(strides).map(|(i, s)| i * s).sum()
+    };
+    let (l, r) = (lhs.parent_values(), rhs.parent_values());
+    let value = |index: &[usize]| {
+        op(
+            l[lhs.offset + offset(index, &lhs_strides)],
+            r[rhs.offset + offset(index, &rhs_strides)],
+        )
+    };
+
+    let valid_combinations =
+        combine_valid_combinations(&[&lhs.valid_combinations(), &rhs.valid_combinations()]);
+    let Some(is_valid) = validity(&granularity, &valid_combinations) else {
+        let mut values = Vec::with_capacity(granularity.len());
+        granularity.for_each_cell(|index| values.push(value(index)));
+        return Data::from_parts(granularity, Values::from(values));
+    };
+    let mut cells = Vec::new();
+    let mut cell = 0;
+    granularity.for_each_cell(|index| {
+        if is_valid(cell) {
+            cells.push((cell, value(index)));
+        }
+        cell += 1;
+    });
+    let mut data = Data::from_sparse(granularity, SparseValues::from_cells(cells, f64::NAN));
+    data.valid_combinations = valid_combinations;
+    data
+}
+
+/// Performs the binary operation `op` between two pieces of `Data`,
+/// broadcasting each operand to the combined granularity of both if
+/// `policy` allows it.
+///
+/// Returns an error if the policy is violated, a dimension has different
+/// values that cannot be aligned as scenarios, or `op` adds, subtracts or
+/// compares values in different units.
+pub fn try_binary_op<L: Operand + ?Sized, R: Operand + ?Sized>(
+    op: BinaryOp,
+    lhs: &L,
+    rhs: &R,
+    policy: &BroadcastPolicy,
+) -> Result<Data> {
+    if let (Some(lhs), Some(rhs)) = (lhs.as_data(), rhs.as_data()) {
+        return try_binary_op_data(op, lhs, rhs, policy);
+    }
+    let (lhs, rhs) = (lhs.view(), rhs.view());
+    // Views cannot be aligned to different scenarios in place.
+    if lhs.granularity().try_broadcast(rhs.granularity()).is_err() {
+        return try_binary_op_data(op, &lhs.to_data(), &rhs.to_data(), policy);
+    }
+    let granularity = policy.check(Some(lhs.granularity()), Some(rhs.granularity()))?;
+    let unit = combine_units(op, lhs.parent.unit(), rhs.parent.unit())?;
+    let granularity = granularity.expect("Both operands have a granularity");
+    let mut data = broadcast_binary_op(&lhs, &rhs, granularity, |a, b| op.apply(a, b));
+    data.unit = unit;
+    data.additivity = combine_additivity(&lhs.parent.additivity, &rhs.parent.additivity);
+    Ok(data.restricted_by(&[&lhs.valid_combinations(), &rhs.valid_combinations()]))
+}
+
+/// Performs `try_binary_op` between two pieces of `Data`.
+fn try_binary_op_data(
+    op: BinaryOp,
+    lhs: &Data,
+    rhs: &Data,
+    policy: &BroadcastPolicy,
+) -> Result<Data> {
+    let operands = align_scenarios(&[lhs, rhs], None)?;
+    let (lhs, rhs) = (&*operands[0], &*operands[1]);
+    let granularity = policy.check(Some(lhs.granularity()), Some(rhs.granularity()))?;
+    let granularity = granularity.expect("Both operands have a granularity");
+    let unit = combine_units(op, lhs.unit(), rhs.unit())?;
+    let mut data = if let Some(data) = sparse_binary_op(op, lhs, rhs) {
+        data
+    } else if lhs.granularity() == rhs.granularity() {
+        Data::from_parts(
+            lhs.granularity().clone(),
+            array_binary_op(lhs.values(), rhs.values(), |a, b| op.apply(a, b)),
+        )
+    } else {
+        broadcast_binary_op(&lhs.view(), &rhs.view(), granularity, |a, b| op.apply(a, b))
+    };
+    data.unit = unit;
+    data.additivity = combine_additivity(&lhs.additivity, &rhs.additivity);
+    Ok(data.restricted_by(&[&lhs.valid_combinations, &rhs.valid_combinations]))
+}
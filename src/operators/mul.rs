@@ -1,14 +1,61 @@
-use crate::Data;
+use crate::{
+    Data,
+    aggregation::combine_additivity,
+    error::Result,
+    scenario::align_scenarios,
+    sparse::sparse_binary_op,
+    unit::{Unit, combine_units},
+};
 
-use super::{array_binary_op, scalar_binary_op};
+use super::{
+    BinaryOp, BroadcastPolicy, Operand, array_binary_op, broadcast_binary_op, scalar_binary_op,
+    try_binary_op,
+};
 
 /// Performs a multiplication operation (*) expanding the granularity of
 /// either operand as required.
 ///
 /// This is often called "broadcasting".  Whether it is correct to broadcast
-/// depends on what the data represents.
-pub fn mul(lhs: &Data, rhs: &Data) -> Data {
-    todo!()
+/// depends on what the data represents, use `try_mul` to enforce a
+/// `BroadcastPolicy`.
+///
+/// The unit of the result is the product of the units of the operands.
+///
+/// # Panics
+///
+/// If a dimension has different values in `lhs` and `rhs` that cannot be
+/// aligned as scenarios.
+pub fn mul<L: Operand + ?Sized, R: Operand + ?Sized>(lhs: &L, rhs: &R) -> Data {
+    let (Some(lhs), Some(rhs)) = (lhs.as_data(), rhs.as_data()) else {
+        // The default policy allows every broadcast and the units of a
+        // product always combine, so this cannot fail.
+        return try_binary_op(BinaryOp::Mul, lhs, rhs, &BroadcastPolicy::default()).unwrap();
+    };
+    let operands = align_scenarios(&[lhs, rhs], None).unwrap_or_else(|e| panic!("{}", e));
+    let (lhs, rhs) = (&*operands[0], &*operands[1]);
+    if lhs.granularity() == rhs.granularity() {
+        return mul_strict(lhs, rhs);
+    }
+    let granularity = lhs.granularity().broadcast(rhs.granularity());
+    let mut data = sparse_binary_op(BinaryOp::Mul, lhs, rhs).unwrap_or_else(|| {
+        broadcast_binary_op(&lhs.view(), &rhs.view(), granularity, |a, b| a * b)
+    });
+    data.unit = mul_units(lhs, rhs);
+    data.additivity = combine_additivity(&lhs.additivity, &rhs.additivity);
+    data.restricted_by(&[&lhs.valid_combinations, &rhs.valid_combinations])
+}
+
+/// Performs a multiplication operation (*) expanding the granularity of
+/// either operand only as allowed by `policy`.
+///
+/// Returns an error if the policy is violated or a dimension has different
+/// values in `lhs` and `rhs`.
+pub fn try_mul<L: Operand + ?Sized, R: Operand + ?Sized>(
+    lhs: &L,
+    rhs: &R,
+    policy: &BroadcastPolicy,
+) -> Result<Data> {
+    try_binary_op(BinaryOp::Mul, lhs, rhs, policy)
 }
 
 /// Performs a muliplication operation (*) but only if the level of
@@ -17,7 +64,19 @@ pub fn mul(lhs: &Data, rhs: &Data) -> Data {
 /// # Panics
 ///
 /// If the granularity of the two operands is not the same.
-pub fn mul_strict(lhs: &Data, rhs: &Data) -> Data {
+pub fn mul_strict<L: Operand + ?Sized, R: Operand + ?Sized>(lhs: &L, rhs: &R) -> Data {
+    let (Some(lhs), Some(rhs)) = (lhs.as_data(), rhs.as_data()) else {
+        let (lhs, rhs) = (lhs.view(), rhs.view());
+        if lhs.granularity() != rhs.granularity() {
+            panic!(
+                "When using the strict version of operators (mul in
+            this case) the granularity must match."
+            )
+        }
+        return mul(&lhs, &rhs);
+    };
+    let operands = align_scenarios(&[lhs, rhs], None).unwrap_or_else(|e| panic!("{}", e));
+    let (lhs, rhs) = (&*operands[0], &*operands[1]);
     if lhs.granularity() != rhs.granularity() {
         panic!(
             "When using the strict version of operators (mul in
@@ -25,25 +84,33 @@ pub fn mul_strict(lhs: &Data, rhs: &Data) -> Data {
         )
     }
 
-    let values = array_binary_op(lhs.values(), rhs.values(), |a, b| a * b);
-    Data {
-        granularity: lhs.granularity().clone(),
-        values,
-    }
+    let mut data = sparse_binary_op(BinaryOp::Mul, lhs, rhs).unwrap_or_else(|| {
+        let values = array_binary_op(lhs.values(), rhs.values(), |a, b| a * b);
+        Data::from_parts(lhs.granularity().clone(), values)
+    });
+    data.unit = mul_units(lhs, rhs);
+    data.additivity = combine_additivity(&lhs.additivity, &rhs.additivity);
+    data.restricted_by(&[&lhs.valid_combinations, &rhs.valid_combinations])
+}
+
+/// Returns the unit of the product of `lhs` and `rhs`, which cannot fail.
+fn mul_units(lhs: &Data, rhs: &Data) -> Option<Unit> {
+    combine_units(BinaryOp::Mul, lhs.unit(), rhs.unit()).unwrap()
 }
 
 /// Adds a scalar `amount` to `data`.
 pub fn mul_scalar(data: &Data, amount: f64) -> Data {
-    let values = scalar_binary_op(data.values(), amount, |a, b| a * b);
-    Data {
-        granularity: data.granularity().clone(),
-        values,
+    if let Some(data) = data.mul_scalar_sparse(amount) {
+        return data;
     }
+    let values = scalar_binary_op(data.values(), amount, |a, b| a * b);
+    Data::from_parts(data.granularity().clone(), values).with_metadata_from(data)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Error;
 
     #[test]
     fn test_mul_strict() {
@@ -57,6 +124,59 @@ mod tests {
         assert_eq!(value, 15.0);
     }
 
+    #[test]
+    fn test_mul_broadcast() {
+        let price = Data::new(
+            "product".to_string(),
+            vec!["A", "B", "C"],
+            vec![1.0, 2.0, 3.0],
+        );
+        let volume = Data::new("region".to_string(), vec!["EU", "US"], vec![10.0, 100.0]);
+        let revenue = mul(&price, &volume);
+
+        assert!(revenue.granularity().varies_by("product"));
+        assert!(revenue.granularity().varies_by("region"));
+        assert_eq!(
+            revenue.values().values().to_vec(),
+            vec![10.0, 20.0, 30.0, 100.0, 200.0, 300.0]
+        );
+    }
+
+    #[test]
+    fn test_try_mul() {
+        let cost = Data::new("product".to_string(), vec!["A", "B"], vec![1.0, 2.0]);
+        let units = Data::new("region".to_string(), vec!["EU", "US"], vec![10.0, 100.0]);
+
+        let policy = BroadcastPolicy::default().max_cells(3);
+        assert_eq!(
+            try_mul(&cost, &units, &policy).err(),
+            Some(Error::TooManyCells { cells: 4, limit: 3 })
+        );
+
+        let policy = BroadcastPolicy::default().lhs_dimensions(&["scenario"]);
+        let error = try_mul(&cost, &units, &policy).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Broadcasting across 'region' is not allowed: \
+             the left hand operand may not be broadcast across it"
+        );
+
+        let policy = BroadcastPolicy::default()
+            .tagged_only()
+            .broadcastable("product");
+        assert!(matches!(
+            try_mul(&cost, &units, &policy),
+            Err(Error::BroadcastNotAllowed { dimension, .. }) if dimension == "region"
+        ));
+
+        let policy = policy.broadcastable("region");
+        let revenue = try_mul(&cost, &units, &policy).unwrap();
+        assert_eq!(
+            revenue.values().values().to_vec(),
+            mul(&cost, &units).values().values().to_vec()
+        );
+    }
+
     #[test]
     fn test_mul_scalar() {
         let data_1 = Data::new_from_iter("test".to_string(), [("A".to_string(), 1.0)].into_iter());
@@ -0,0 +1,195 @@
+//! Contains the policy that controls when the operators may broadcast.
+//!
+//! A policy is checked for each binary operation, before any values are
+//! computed.  The operands of a conditional, see `Expr::If` and
+//! `Formula::If`, are checked from left to right: the condition is the left
+//! hand operand and `then` the right, and their combined granularity is then
+//! the left hand operand with `otherwise` as the right.
+
+use std::collections::HashSet;
+
+use crate::{
+    Granularity,
+    error::{Error, Result},
+};
+
+/// Controls when the operators may broadcast an operand, see `try_mul`.
+///
+/// The default policy allows every broadcast, the same as `mul`.
+#[derive(Debug, Clone, Default)]
+pub struct BroadcastPolicy {
+    /// The maximum number of cells in the result.
+    max_cells: Option<usize>,
+
+    /// The dimensions the left hand operand may be broadcast across.
+    lhs_dimensions: Option<HashSet<String>>,
+
+    /// The dimensions the right hand operand may be broadcast across.
+    rhs_dimensions: Option<HashSet<String>>,
+
+    /// The dimensions that are tagged as broadcastable.
+    broadcastable: HashSet<String>,
+
+    /// Whether to only allow broadcasting across tagged dimensions.
+    tagged_only: bool,
+}
+
+impl BroadcastPolicy {
+    /// Builder type API for limiting the number of cells in the result.
+    pub fn max_cells(mut self, max_cells: usize) -> Self {
+        self.max_cells = Some(max_cells);
+        self
+    }
+
+    /// Builder type API for listing the only dimensions that the left hand
+    /// operand may be broadcast across.
+    pub fn lhs_dimensions(mut self, dimension_names: &[&str]) -> Self {
+        self.lhs_dimensions = Some(dimension_names.iter().map(|d| d.to_string()).collect());
+        self
+    }
+
+    /// Builder type API for listing the only dimensions that the right hand
+    /// operand may be broadcast across.
+    pub fn rhs_dimensions(mut self, dimension_names: &[&str]) -> Self {
+        self.rhs_dimensions = Some(dimension_names.iter().map(|d| d.to_string()).collect());
+        self
+    }
+
+    /// Builder type API for tagging a dimension as broadcastable, e.g. a
+    /// scenario dimension that any figure can be repeated across.
+    pub fn broadcastable(mut self, dimension_name: &str) -> Self {
+        self.broadcastable.insert(dimension_name.to_string());
+        self
+    }
+
+    /// Builder type API for only allowing broadcasts across dimensions that
+    /// are tagged as broadcastable.
+    pub fn tagged_only(mut self) -> Self {
+        self.tagged_only = true;
+        self
+    }
+
+    /// Checks that an operation between operands of granularity `lhs` and
+    /// `rhs` is allowed, returning the granularity of the result.
+    ///
+    /// A `None` operand is a scalar, which is always allowed to broadcast,
+    /// although the result must still respect the limit on its cells.
+    pub(crate) fn check(
+        &self,
+        lhs: Option<&Granularity>,
+        rhs: Option<&Granularity>,
+    ) -> Result<Option<Granularity>> {
+        let result = match (lhs, rhs) {
+            (Some(lhs), Some(rhs)) if lhs == rhs => lhs.clone(),
+            (Some(lhs), Some(rhs)) => lhs.try_broadcast(rhs)?,
+            (Some(granularity), None) | (None, Some(granularity)) => granularity.clone(),
+            (None, None) => return Ok(None),
+        };
+
+        if let Some(limit) = self.max_cells
+            && result.len() > limit
+        {
+            return Err(Error::TooManyCells {
+                cells: result.len(),
+                limit,
+            });
+        }
+
+        let operands = [
+            ("left", lhs, &self.lhs_dimensions),
+            ("right", rhs, &self.rhs_dimensions),
+        ];
+        for (side, granularity, allowed) in operands {
+            let Some(granularity) = granularity else {
+                continue;
+            };
+            for (name, _) in result.dimensions() {
+                if granularity.maybe_varies_by(name) {
+                    continue;
+                }
+                let reason = if allowed.as_ref().is_some_and(|a| !a.contains(name)) {
+                    format!("the {} hand operand may not be broadcast across it", side)
+                } else if self.tagged_only && !self.broadcastable.contains(name) {
+                    "it is not tagged as broadcastable".to_string()
+                } else {
+                    continue;
+                };
+                return Err(Error::BroadcastNotAllowed {
+                    dimension: name.to_string(),
+                    reason,
+                });
+            }
+        }
+        Ok(Some(result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Data;
+
+    #[test]
+    fn test_max_cells() {
+        let price = Data::new("product".to_string(), vec!["A", "B"], vec![1.0, 2.0]);
+        let volume = Data::new("region".to_string(), vec!["EU", "US"], vec![10.0, 20.0]);
+        let (price, volume) = (price.granularity(), volume.granularity());
+        let policy = BroadcastPolicy::default().max_cells(3);
+        assert!(matches!(
+            policy.check(Some(price), Some(volume)),
+            Err(Error::TooManyCells { cells: 4, limit: 3 })
+        ));
+
+        // The limit applies even if the other operand is a scalar.
+        let policy = BroadcastPolicy::default().max_cells(1);
+        assert!(matches!(
+            policy.check(Some(price), None),
+            Err(Error::TooManyCells { cells: 2, limit: 1 })
+        ));
+        assert!(matches!(
+            policy.check(None, Some(price)),
+            Err(Error::TooManyCells { cells: 2, limit: 1 })
+        ));
+        assert!(policy.check(None, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_dimensions_per_side() {
+        let headcount = Data::new("team".to_string(), vec!["Ops", "Tech"], vec![3.0, 5.0]);
+        let salary = Data::new("site".to_string(), vec!["Lyon", "Oslo"], vec![4.0, 6.0]);
+        let (headcount, salary) = (headcount.granularity(), salary.granularity());
+        let policy = BroadcastPolicy::default().lhs_dimensions(&["site"]);
+        assert!(policy.check(Some(headcount), Some(salary)).is_ok());
+        let error = policy.check(Some(salary), Some(headcount)).unwrap_err();
+        assert!(
+            matches!(error, Error::BroadcastNotAllowed { dimension, .. } if dimension == "team")
+        );
+
+        let policy = BroadcastPolicy::default().rhs_dimensions(&[]);
+        assert!(policy.check(Some(headcount), Some(headcount)).is_ok());
+        assert!(policy.check(Some(headcount), None).is_ok());
+        assert!(matches!(
+            policy.check(Some(headcount), Some(salary)),
+            Err(Error::BroadcastNotAllowed { .. })
+        ));
+    }
+
+    #[test]
+    fn test_tagged_only() {
+        let amount = Data::new("month".to_string(), vec!["Jan", "Feb"], vec![7.0, 8.0]);
+        let rate = Data::new(
+            "currency".to_string(),
+            vec!["EUR", "GBP", "USD"],
+            vec![1.1, 1.3, 1.0],
+        );
+        let (amount, rate) = (amount.granularity(), rate.granularity());
+        let policy = BroadcastPolicy::default().tagged_only();
+        assert!(matches!(
+            policy.check(Some(amount), Some(rate)),
+            Err(Error::BroadcastNotAllowed { .. })
+        ));
+        let policy = policy.broadcastable("month").broadcastable("currency");
+        let result = policy.check(Some(amount), Some(rate)).unwrap().unwrap();
+        assert_eq!(result.len(), 6);
+    }
+}
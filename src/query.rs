@@ -1,6 +1,8 @@
+use crate::granularity::DimensionValue;
+
 pub struct Query {
     pub dimension_name: String,
-    pub dimension_value: String,
+    pub dimension_value: DimensionValue,
 }
 
 #[cfg(test)]
@@ -18,12 +20,35 @@ mod tests {
 
         let query = Query {
             dimension_name: "test".to_string(),
-            dimension_value: "B".to_string(),
+            dimension_value: "B".into(),
         };
 
         let data = data.query(&query);
-        let values = data.values;
+        let values = data.values();
         assert_eq!(values.len(), 1);
         assert_eq!(values.value(0), 2.0);
     }
+
+    #[test]
+    fn test_multiple_dimensions() {
+        let data = Data::from_rows(
+            &["region", "product"],
+            [
+                (vec!["EU", "A"], 1.0),
+                (vec!["EU", "B"], 2.0),
+                (vec!["US", "A"], 3.0),
+                (vec!["US", "B"], 4.0),
+            ],
+        )
+        .unwrap();
+
+        let query = Query {
+            dimension_name: "product".to_string(),
+            dimension_value: "B".into(),
+        };
+
+        let data = data.query(&query);
+        assert!(!data.granularity().varies_by("product"));
+        assert_eq!(data.values().values().to_vec(), vec![2.0, 4.0]);
+    }
 }
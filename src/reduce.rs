@@ -0,0 +1,189 @@
+//! Contains the reduction of `Data` across a dimension.
+//!
+//! The `Additivity` of the data along the dimension decides the default
+//! aggregation and rules out aggregations that make no sense, such as
+//! summing prices across regions.
+
+use crate::{
+    Data,
+    aggregation::Aggregation,
+    data::Values,
+    error::{Error, Result},
+};
+
+impl Data {
+    /// Aggregates the values across the dimension `dimension_name` using
+    /// `aggregation`, so the result no longer varies by it.
+    ///
+    /// Returns an error if `aggregation` does not suit the additivity of the
+    /// data along the dimension.
+    pub fn reduce(&self, dimension_name: &str, aggregation: Aggregation) -> Result<Data> {
+        self.check_aggregation(dimension_name, aggregation)?;
+        let idx = self
+            .granularity
+            .dimension_index(dimension_name)
+            .ok_or_else(|| Error::UnknownDimension(dimension_name.to_string()))?;
+        if !self.granularity.varies_by(dimension_name) {
+            return Ok(self.clone());
+        }
+
+        let mut granularity = self.granularity.clone();
+        granularity.drop(dimension_name);
+        let is_valid = self.validity();
+        if is_valid.is_none()
+            && let Some(mut data) = self.reduce_sparse(dimension_name, aggregation, &granularity)
+        {
+            data.additivity.remove(dimension_name);
+            return Ok(data);
+        }
+        let run_lengths = self.granularity.run_lengths_for(&granularity);
+        let run_length = *self.granularity.run_length(dimension_name);
+        let count = self
+            .granularity
+            .dimension_values(dimension_name)
+            .map(|values| values.len())
+            .unwrap_or_default();
+
+        let source = self.values().values();
+        let mut values = Vec::with_capacity(granularity.len());
+        granularity.for_each_cell(|index| {
+            let base: usize = index.iter().zip(&run_lengths).map(|(i, r)| i * r).sum();
+            debug_assert_eq!(index[idx], 0);
+            // Invalid cells are skipped rather than read as `NaN`, so e.g. a
+            // mean is over the valid values of the dimension only.
+            let contributions = (0..count)
+                .map(|i| base + i * run_length)
+                .filter(|offset| is_valid.as_ref().is_none_or(|is_valid| is_valid(*offset)))
+                .map(|offset| (source[offset], 1.0));
+            values.push(aggregation.aggregate_weighted(contributions));
+        });
+
+        let mut data = Data::from_parts(granularity, Values::from(values)).with_metadata_from(self);
+        data.additivity.remove(dimension_name);
+        Ok(data.apply_valid_combinations())
+    }
+
+    /// Aggregates the values across the dimension `dimension_name` using the
+    /// default aggregation for the additivity of the data along it.
+    pub fn roll_up(&self, dimension_name: &str) -> Result<Data> {
+        let aggregation = self.additivity(dimension_name).default_aggregation();
+        self.reduce(dimension_name, aggregation)
+    }
+
+    /// Checks that `aggregation` suits the additivity of the data along the
+    /// dimension `dimension_name`.
+    pub(crate) fn check_aggregation(
+        &self,
+        dimension_name: &str,
+        aggregation: Aggregation,
+    ) -> Result<()> {
+        let additivity = self.additivity(dimension_name);
+        if additivity.allows(aggregation) {
+            Ok(())
+        } else {
+            Err(Error::InvalidAggregation {
+                dimension: dimension_name.to_string(),
+                aggregation,
+                additivity,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Additivity, Expr, Period,
+        operators::{BinaryOp, BroadcastPolicy, mul, try_binary_op},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_roll_up_defaults() {
+        let months: Vec<_> = (1..=3).map(|m| Period::monthly(2024, m)).collect();
+        let time = Data::new("time".to_string(), months, vec![1.0, 2.0, 3.0]);
+        let region = Data::new("region".to_string(), vec!["EU", "US"], vec![10.0, 100.0]);
+        let balance = mul(&time, &region)
+            .with_additivity("time", Additivity::SemiAdditive)
+            .with_additivity("region", Additivity::Additive);
+
+        let by_region = balance.roll_up("time").unwrap();
+        assert!(!by_region.granularity().varies_by("time"));
+        assert_eq!(by_region.values().values().to_vec(), vec![30.0, 300.0]);
+
+        let by_time = balance.roll_up("region").unwrap();
+        assert_eq!(
+            by_time.values().values().to_vec(),
+            vec![110.0, 220.0, 330.0]
+        );
+        assert_eq!(by_time.additivity("time"), Additivity::SemiAdditive);
+    }
+
+    #[test]
+    fn test_reduce_relayouts_remaining_dimensions() {
+        let months: Vec<_> = (1..=4).map(|m| Period::monthly(2024, m)).collect();
+        let level = Data::new("time".to_string(), months, vec![5.0, 6.0, 7.0, 8.0]);
+        let scale = Data::new(
+            "warehouse".to_string(),
+            vec!["Leeds", "York"],
+            vec![1.0, 3.0],
+        );
+        let stock = mul(&level, &scale).with_additivity("time", Additivity::SemiAdditive);
+
+        let by_warehouse = stock.reduce("time", Aggregation::Last).unwrap();
+        assert_eq!(*by_warehouse.granularity().run_length("warehouse"), 1);
+        assert_eq!(by_warehouse.values().values().to_vec(), vec![8.0, 24.0]);
+        let table = by_warehouse.to_string();
+        assert!(table.contains("York") && table.contains("24"));
+    }
+
+    #[test]
+    fn test_refuses_nonsensical_aggregation() {
+        let price = Data::new("region".to_string(), vec!["EU", "US"], vec![2.0, 4.0])
+            .with_additivity("region", Additivity::NonAdditive);
+
+        assert_eq!(
+            price
+                .reduce("region", Aggregation::Sum)
+                .unwrap_err()
+                .to_string(),
+            "Cannot aggregate using Sum across 'region' which is non-additive"
+        );
+        assert_eq!(price.roll_up("region").unwrap().values().value(0), 3.0);
+
+        let months: Vec<_> = (1..=3).map(|m| Period::monthly(2024, m)).collect();
+        let balance = Data::new("time".to_string(), months, vec![4.0, 2.0, 9.0])
+            .with_additivity("time", Additivity::SemiAdditive);
+        assert!(matches!(
+            balance.resample("time", crate::Frequency::Quarterly, Aggregation::Sum),
+            Err(Error::InvalidAggregation { .. })
+        ));
+    }
+
+    #[test]
+    fn test_operators_keep_additivity() {
+        let price = Data::new("region".to_string(), vec!["EU", "US"], vec![2.0, 4.0])
+            .with_additivity("region", Additivity::NonAdditive);
+        let policy = BroadcastPolicy::default();
+
+        // The operands agree so the sum of prices is still a price.
+        let total = try_binary_op(BinaryOp::Add, &price, &price, &policy).unwrap();
+        assert_eq!(total.additivity("region"), Additivity::NonAdditive);
+        assert!(total.reduce("region", Aggregation::Sum).is_err());
+        let total = (Expr::from(&price) + &price).evaluate().unwrap();
+        assert_eq!(total.additivity("region"), Additivity::NonAdditive);
+
+        let volume = Data::new("region".to_string(), vec!["EU", "US"], vec![10.0, 20.0]);
+        let revenue = mul(&price, &volume);
+        assert_eq!(revenue.additivity("region"), Additivity::Additive);
+        assert_eq!(
+            revenue
+                .reduce("region", Aggregation::Sum)
+                .unwrap()
+                .values()
+                .value(0),
+            100.0
+        );
+    }
+}
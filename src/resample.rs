@@ -0,0 +1,313 @@
+//! Contains calendar aware resampling of time dimensions.
+//!
+//! A time dimension holds either dates (daily data) or periods of a
+//! single frequency.  Resampling to a coarser frequency aggregates the
+//! values that fall within each new period, while resampling to a finer
+//! frequency disaggregates each value across the periods it contains.
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+
+use crate::{
+    Data,
+    aggregation::Aggregation,
+    data::Values,
+    error::{Error, Result},
+    granularity::{DimensionValue, Frequency, Granularity, Period},
+};
+
+/// The frequency that `Data::resample` resamples a time dimension to,
+/// either dates or periods of a `Frequency`.
+///
+/// Targets are ordered from finest to coarsest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ResampleTo {
+    Daily,
+    Period(Frequency),
+}
+
+impl From<Frequency> for ResampleTo {
+    fn from(frequency: Frequency) -> Self {
+        ResampleTo::Period(frequency)
+    }
+}
+
+impl Data {
+    /// Resamples the time dimension `time_dimension` to `frequency`.
+    ///
+    /// When `frequency` is coarser than the current frequency, the values
+    /// within each new period are combined using `aggregation`.
+    ///
+    /// When `frequency` is finer, each value is disaggregated across the
+    /// periods it contains.  With `Aggregation::Sum` the value is split in
+    /// proportion to the number of days in each new period, otherwise the
+    /// value is repeated.
+    ///
+    /// Fiscal periods keep their fiscal year start, dates are resampled to
+    /// calendar periods.  Data that does not vary by the time dimension
+    /// applies to every period, so only the values of the dimension change.
+    ///
+    /// Returns an error if `aggregation` does not suit the additivity of the
+    /// time dimension, e.g. summing a balance.
+    pub fn resample(
+        &self,
+        time_dimension: &str,
+        frequency: impl Into<ResampleTo>,
+        aggregation: Aggregation,
+    ) -> Result<Data> {
+        let frequency = frequency.into();
+        self.check_aggregation(time_dimension, aggregation)?;
+        let values = self
+            .granularity
+            .dimension_values(time_dimension)
+            .ok_or_else(|| Error::UnknownDimension(time_dimension.to_string()))?;
+        let (current, fiscal_year_start) = time_frequency(time_dimension, values.iter())?;
+        if current == frequency {
+            return Ok(self.clone());
+        }
+
+        // Maps each new value to the (index, weight) of the values it is built from.
+        let mut groups: BTreeMap<DimensionValue, Vec<(usize, f64)>> = BTreeMap::new();
+        for (idx, value) in values.iter().enumerate() {
+            match frequency {
+                ResampleTo::Period(target) if frequency > current => {
+                    let period = Period::containing(first_day(value), target, fiscal_year_start);
+                    groups.entry(period.into()).or_default().push((idx, 1.0));
+                }
+                _ => {
+                    let days = days_in(value) as f64;
+                    for sub in subdivide(value, frequency) {
+                        let weight = match aggregation {
+                            Aggregation::Sum => days_in(&sub) as f64 / days,
+                            _ => 1.0,
+                        };
+                        groups.entry(sub).or_default().push((idx, weight));
+                    }
+                }
+            }
+        }
+        let (new_values, groups): (Vec<_>, Vec<_>) = groups.into_iter().unzip();
+
+        let dimensions = self
+            .granularity
+            .dimensions_with_flags()
+            .into_iter()
+            .map(|(name, values, varies)| {
+                if name == time_dimension {
+                    (name, new_values.clone().into(), varies)
+                } else {
+                    (name, values, varies)
+                }
+            })
+            .collect();
+        let granularity = Granularity::from_dimensions(dimensions);
+        if !self.granularity.varies_by(time_dimension) {
+            return Ok(Data::from_parts(granularity, self.values().clone())
+                .with_metadata_from(self)
+                .forget_valid_combinations_of(time_dimension));
+        }
+
+        let run_lengths = self.granularity.run_lengths_for(&granularity);
+        let time_idx = granularity
+            .dimension_index(time_dimension)
+            .expect("Time dimension is retained");
+        let time_run_length = run_lengths[time_idx];
+
+        let source = self.values().values();
+        let is_valid = self.validity();
+        let mut values = Vec::with_capacity(granularity.len());
+        granularity.for_each_cell(|index| {
+            let base: usize = index
+                .iter()
+                .zip(run_lengths.iter())
+                .enumerate()
+                .filter(|(dim, _)| *dim != time_idx)
+                .map(|(_, (i, run_length))| i * run_length)
+                .sum();
+            // An invalid source period contributes neither its value nor
+            // its weight to the resampled period.
+            let contributions = groups[index[time_idx]]
+                .iter()
+                .map(|(i, weight)| (base + i * time_run_length, *weight))
+                .filter(|(offset, _)| is_valid.as_ref().is_none_or(|is_valid| is_valid(*offset)))
+                .map(|(offset, weight)| (source[offset], weight));
+            values.push(aggregation.aggregate_weighted(contributions));
+        });
+
+        Ok(Data::from_parts(granularity, Values::from(values))
+            .with_metadata_from(self)
+            .forget_valid_combinations_of(time_dimension)
+            .apply_valid_combinations())
+    }
+}
+
+/// Determines the frequency and fiscal year start of a time dimension.
+fn time_frequency<'a>(
+    dimension: &str,
+    mut values: impl Iterator<Item = &'a DimensionValue>,
+) -> Result<(ResampleTo, u32)> {
+    let invalid = |reason: &str| Error::InvalidTimeDimension {
+        dimension: dimension.to_string(),
+        reason: reason.to_string(),
+    };
+
+    let first = match values.next() {
+        Some(DimensionValue::Date(_)) => (ResampleTo::Daily, 1),
+        Some(DimensionValue::Period(p)) => (p.frequency().into(), p.fiscal_year_start()),
+        Some(_) => return Err(invalid("values must be dates or periods")),
+        None => return Err(invalid("it has no values")),
+    };
+    for value in values {
+        let next = match value {
+            DimensionValue::Date(_) => (ResampleTo::Daily, 1),
+            DimensionValue::Period(p) => (p.frequency().into(), p.fiscal_year_start()),
+            _ => unreachable!("Dimension values are all the same kind"),
+        };
+        if next != first {
+            return Err(invalid(
+                "periods must share a frequency and fiscal year start",
+            ));
+        }
+    }
+    Ok(first)
+}
+
+fn first_day(value: &DimensionValue) -> NaiveDate {
+    match value {
+        DimensionValue::Date(date) => *date,
+        DimensionValue::Period(period) => period.first_day(),
+        _ => unreachable!("Time dimensions hold dates or periods"),
+    }
+}
+
+fn days_in(value: &DimensionValue) -> i64 {
+    match value {
+        DimensionValue::Date(_) => 1,
+        DimensionValue::Period(period) => period.days(),
+        _ => unreachable!("Time dimensions hold dates or periods"),
+    }
+}
+
+/// Splits the period `value` into the dates or periods of `frequency` that
+/// it contains.
+fn subdivide(value: &DimensionValue, frequency: ResampleTo) -> Vec<DimensionValue> {
+    let period = value.as_period().expect("Only periods can be subdivided");
+    match frequency {
+        ResampleTo::Daily => period
+            .first_day()
+            .iter_days()
+            .take_while(|date| *date <= period.last_day())
+            .map(DimensionValue::from)
+            .collect(),
+        ResampleTo::Period(frequency) => {
+            let fiscal_year_start = period.fiscal_year_start();
+            let start = Period::containing(period.first_day(), frequency, fiscal_year_start);
+            let end = Period::containing(period.last_day(), frequency, fiscal_year_start);
+            Period::range(start, end)
+                .map(DimensionValue::from)
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_daily_to_monthly() {
+        let start = date(2024, 1, 30);
+        let data = Data::new_from_iter(
+            "time".to_string(),
+            start.iter_days().take(4).map(|d| (d, 1.0)),
+        );
+        let monthly = data
+            .resample("time", Frequency::Monthly, Aggregation::Sum)
+            .unwrap();
+
+        let values = monthly.granularity().dimension_values("time").unwrap();
+        assert_eq!(
+            values.iter().cloned().collect::<Vec<_>>(),
+            vec![
+                Period::monthly(2024, 1).into(),
+                Period::monthly(2024, 2).into()
+            ]
+        );
+        assert_eq!(monthly.values().values().to_vec(), vec![2.0, 2.0]);
+    }
+
+    #[test]
+    fn test_monthly_to_quarterly_mean() {
+        let data = Data::new_from_iter(
+            "time".to_string(),
+            Period::range(Period::monthly(2024, 1), Period::monthly(2024, 6))
+                .zip([1.0, 2.0, 3.0, 4.0, 5.0, 6.0]),
+        );
+        let quarterly = data
+            .resample("time", Frequency::Quarterly, Aggregation::Mean)
+            .unwrap();
+        assert_eq!(quarterly.values().values().to_vec(), vec![2.0, 5.0]);
+    }
+
+    #[test]
+    fn test_disaggregate_by_days() {
+        let data = Data::new(
+            "time".to_string(),
+            vec![Period::quarterly(2024, 1)],
+            vec![91.0],
+        );
+
+        let monthly = data
+            .resample("time", Frequency::Monthly, Aggregation::Sum)
+            .unwrap();
+        let expected = [31.0, 29.0, 31.0];
+        for (actual, expected) in monthly.values().values().iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-9);
+        }
+
+        let daily = data
+            .resample("time", ResampleTo::Daily, Aggregation::Last)
+            .unwrap();
+        assert_eq!(daily.values().len(), 91);
+        assert!(daily.values().values().iter().all(|v| *v == 91.0));
+    }
+
+    #[test]
+    fn test_constant_over_time() {
+        let monthly: Vec<_> =
+            Period::range(Period::monthly(2024, 1), Period::monthly(2024, 6)).collect();
+        let time = Data::new("time".to_string(), monthly, vec![1.0; 6]);
+        let price = Data::new("region".to_string(), vec!["EU", "US"], vec![2.0, 3.0]);
+        let price = price
+            .expand_to(&time.granularity().broadcast(price.granularity()))
+            .unwrap()
+            .squeeze();
+        assert!(!price.granularity().varies_by("time"));
+
+        let quarterly = price
+            .resample("time", Frequency::Quarterly, Aggregation::Sum)
+            .unwrap();
+        assert!(!quarterly.granularity().varies_by("time"));
+        assert_eq!(
+            quarterly
+                .granularity()
+                .dimension_values("time")
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(quarterly.values().values().to_vec(), vec![2.0, 3.0]);
+    }
+
+    #[test]
+    fn test_non_temporal_dimension() {
+        let data = Data::new("region".to_string(), vec!["EU", "US"], vec![1.0, 2.0]);
+        let result = data.resample("region", Frequency::Monthly, Aggregation::Sum);
+        assert!(matches!(result, Err(Error::InvalidTimeDimension { .. })));
+    }
+}
@@ -0,0 +1,563 @@
+//! Contains scenarios, sparse overrides of the values of a piece of `Data`.
+//!
+//! Overriding a value adds a `scenario` dimension, or another dimension
+//! chosen with `with_override_in`, whose first value is `base`.  Only the
+//! base values are stored along with each override, see
+//! `Storage::Scenarios`.  The values of every scenario are expanded the
+//! first time they are needed, the same as sparse data, so every operation
+//! sees the scenario dimension and results carry it automatically.
+//!
+//! Operands that were given different scenarios are aligned first, where an
+//! operand does not have a scenario it takes its `base` values.  Only the
+//! `scenario` dimension and the dimension an operand's overrides are in are
+//! aligned, any other dimension with different values is a conflict.  It is
+//! an error if only some operands have a `base` scenario, e.g. when
+//! "actual" and "budget" are stacked under `scenario` too.
+
+use std::borrow::Cow;
+
+use crate::{
+    Data, DimensionValue, DimensionValues, Granularity,
+    data::{Storage, Values},
+    error::{Error, Result},
+};
+
+/// The name of the dimension that the overrides are expanded into, unless
+/// another is chosen with `with_override_in`.
+pub(crate) const SCENARIO_DIMENSION: &str = "scenario";
+
+/// The scenario that holds the values without any overrides.
+pub(crate) const BASE_SCENARIO: &str = "base";
+
+/// Replaces the value of every cell that matches `selector` in `scenario`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Override {
+    pub(crate) scenario: String,
+    pub(crate) selector: Vec<(String, DimensionValue)>,
+    pub(crate) value: f64,
+}
+
+/// The stored values of data with overrides, see `Storage::Scenarios`.
+#[derive(Clone)]
+pub(crate) struct ScenarioValues {
+    /// The data without the scenario dimension.
+    pub(crate) base: Data,
+
+    /// The name of the scenario dimension.
+    pub(crate) dimension: String,
+
+    /// The overrides, later overrides take priority over earlier ones.
+    pub(crate) overrides: Vec<Override>,
+}
+
+impl ScenarioValues {
+    /// Returns the granularity of the base values along with the scenario
+    /// dimension, whose values are `base` and then each overridden scenario.
+    fn granularity(&self) -> Granularity {
+        let mut labels: Vec<DimensionValue> = vec![BASE_SCENARIO.into()];
+        for o in &self.overrides {
+            let label = DimensionValue::from(o.scenario.as_str());
+            if !labels.contains(&label) {
+                labels.push(label);
+            }
+        }
+        let mut dimensions = self.base.granularity.dimensions_with_flags();
+        dimensions.push((self.dimension.clone(), DimensionValues::new(labels), true));
+        Granularity::from_dimensions(dimensions)
+    }
+
+    /// Expands the base values and the overrides into the values of every
+    /// cell of `granularity`, which is the granularity of the base values
+    /// along with the scenario dimension.
+    ///
+    /// Each override only visits the cells its selector matches.
+    pub(crate) fn to_dense(&self, granularity: &Granularity) -> Values {
+        let labels = granularity.dimension_values(&self.dimension).unwrap();
+        let run_lengths = self.base.granularity.run_lengths_for(granularity);
+        let source = self.base.values().values();
+        let mut values = Vec::with_capacity(granularity.len());
+        granularity.for_each_cell(|index| {
+            let offset: usize = index.iter().zip(&run_lengths).map(|(i, r)| i * r).sum();
+            values.push(source[offset]);
+        });
+
+        for o in &self.overrides {
+            let Some(scenario) = labels.position(&DimensionValue::from(o.scenario.as_str())) else {
+                continue;
+            };
+            // The offset of the first matching cell, along with the
+            // run-length and size of each dimension the selector leaves free.
+            let mut start = scenario * *granularity.run_length(&self.dimension);
+            let mut free = Vec::new();
+            for (name, dimension_values) in granularity.dimensions() {
+                let run_length = *granularity.run_length(name);
+                match o.selector.iter().find(|(selected, _)| selected == name) {
+                    Some((_, value)) => {
+                        start += dimension_values.position(value).unwrap() * run_length;
+                    }
+                    None if name != self.dimension => {
+                        free.push((run_length, dimension_values.len()));
+                    }
+                    None => {}
+                }
+            }
+            let matches: usize = free.iter().map(|(_, size)| size).product();
+            for i in 0..matches {
+                let mut remainder = i;
+                let mut offset = start;
+                for (run_length, size) in &free {
+                    offset += (remainder % size) * run_length;
+                    remainder /= size;
+                }
+                values[offset] = o.value;
+            }
+        }
+        Values::from(values)
+    }
+}
+
+impl Data {
+    /// Builder type API for overriding the value of every cell that matches
+    /// `selector` in the scenario `scenario`, e.g.
+    /// `with_override("downside", &[("region", "EU")], 0.8)`.
+    ///
+    /// The data varies by the `scenario` dimension from then on, although
+    /// only the base values and the overrides are stored until the values
+    /// are needed.  Later overrides take priority over earlier ones.
+    /// Returns an error if the selector refers to a dimension or value the
+    /// data does not have.
+    pub fn with_override<V: Into<DimensionValue> + Clone>(
+        self,
+        scenario: &str,
+        selector: &[(&str, V)],
+        value: f64,
+    ) -> Result<Self> {
+        self.with_override_in(SCENARIO_DIMENSION, scenario, selector, value)
+    }
+
+    /// Builder type API for overriding values, see `with_override`, in the
+    /// scenario dimension `dimension_name` rather than `scenario`, e.g. when
+    /// the data, or data it is combined with, already has a `scenario`
+    /// dimension.
+    ///
+    /// Returns an error if the data already has overrides in another
+    /// dimension.
+    pub fn with_override_in<V: Into<DimensionValue> + Clone>(
+        mut self,
+        dimension_name: &str,
+        scenario: &str,
+        selector: &[(&str, V)],
+        value: f64,
+    ) -> Result<Self> {
+        if let Storage::Scenarios(scenarios, _) = &self.storage
+            && scenarios.dimension != dimension_name
+        {
+            return Err(Error::InvalidGranularity(format!(
+                "the overrides are in the '{}' dimension",
+                scenarios.dimension
+            )));
+        }
+        let (base, _) = self.base_and_overrides();
+        let o = Override::new(&base.granularity, dimension_name, scenario, selector, value)?;
+
+        let storage = std::mem::replace(
+            &mut self.storage,
+            Storage::Dense(Values::from(Vec::<f64>::new())),
+        );
+        let mut scenarios = match storage {
+            Storage::Scenarios(scenarios, _) => *scenarios,
+            storage => {
+                let mut base =
+                    Data::from_parts(self.granularity.clone(), Values::from(Vec::<f64>::new()));
+                base.storage = storage;
+                ScenarioValues {
+                    base,
+                    dimension: dimension_name.to_string(),
+                    overrides: Vec::new(),
+                }
+            }
+        };
+        scenarios.overrides.push(o);
+        self.granularity = scenarios.granularity();
+        self.storage = Storage::Scenarios(Box::new(scenarios), Default::default());
+        Ok(self)
+    }
+
+    /// Returns the names of the scenarios, the values of the scenario
+    /// dimension starting with `base`, or nothing if the data does not vary
+    /// by it.
+    pub fn scenarios(&self) -> Vec<String> {
+        let dimension = match &self.storage {
+            Storage::Scenarios(scenarios, _) => scenarios.dimension.as_str(),
+            _ => SCENARIO_DIMENSION,
+        };
+        match self.granularity.dimension_values(dimension) {
+            Some(values) if self.granularity.maybe_varies_by(dimension) => {
+                values.iter().map(|value| value.to_string()).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Expands the overrides into the values of every scenario, which
+    /// `values` otherwise does the first time they are needed.
+    pub fn expand_scenarios(&self) -> Data {
+        match &self.storage {
+            Storage::Scenarios(..) => {
+                Data::from_parts(self.granularity.clone(), self.values().clone())
+                    .with_metadata_from(self)
+            }
+            _ => self.clone(),
+        }
+    }
+
+    /// Returns the data without its overrides along with the overrides,
+    /// which are empty if it has none.
+    pub(crate) fn base_and_overrides(&self) -> (&Data, &[Override]) {
+        match &self.storage {
+            Storage::Scenarios(scenarios, _) => (&scenarios.base, &scenarios.overrides),
+            _ => (self, &[]),
+        }
+    }
+
+    /// Returns the name of the dimension the overrides are in, if any.
+    #[cfg(feature = "serde")]
+    pub(crate) fn scenario_dimension(&self) -> Option<&str> {
+        match &self.storage {
+            Storage::Scenarios(scenarios, _) => Some(&scenarios.dimension),
+            _ => None,
+        }
+    }
+
+    /// Applies `overrides`, as returned by `base_and_overrides`, in the
+    /// scenario dimension `dimension_name`, or `scenario` if not given.
+    #[cfg(feature = "serde")]
+    pub(crate) fn with_overrides(
+        mut self,
+        dimension_name: Option<&str>,
+        overrides: &[Override],
+    ) -> Result<Self> {
+        let dimension_name = dimension_name.unwrap_or(SCENARIO_DIMENSION);
+        for o in overrides {
+            let selector: Vec<(&str, DimensionValue)> = o
+                .selector
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.clone()))
+                .collect();
+            self = self.with_override_in(dimension_name, &o.scenario, &selector, o.value)?;
+        }
+        Ok(self)
+    }
+
+    /// Returns the data with a `dimension_name` dimension that has exactly
+    /// the values `labels`, taking the `base` values where a scenario is
+    /// missing.
+    ///
+    /// Data that does not vary by `dimension_name` is returned as is,
+    /// otherwise it must have a `base` value for it.
+    fn expand_scenarios_to(
+        &self,
+        dimension_name: &str,
+        labels: &[DimensionValue],
+    ) -> Cow<'_, Data> {
+        let base = DimensionValue::from(BASE_SCENARIO);
+        let Some(current) = self.granularity.dimension_values(dimension_name) else {
+            return Cow::Borrowed(self);
+        };
+        if !self.granularity.maybe_varies_by(dimension_name) || current.iter().eq(labels) {
+            return Cow::Borrowed(self);
+        }
+
+        let mut dimensions = self.granularity.dimensions_with_flags();
+        dimensions.retain(|(name, _, _)| name != dimension_name);
+        dimensions.push((
+            dimension_name.to_string(),
+            DimensionValues::new(labels.to_vec()),
+            true,
+        ));
+        let granularity = Granularity::from_dimensions(dimensions);
+
+        // Overrides only need their scenarios relabelled.
+        if let Storage::Scenarios(scenarios, _) = &self.storage
+            && scenarios.dimension == dimension_name
+        {
+            let mut data = self.clone();
+            data.granularity = granularity;
+            data.storage = Storage::Scenarios(scenarios.clone(), Default::default());
+            return Cow::Owned(data);
+        }
+
+        // The position in `self` that each scenario reads its values from.
+        let idx = granularity.dimension_index(dimension_name).unwrap();
+        let positions: Vec<usize> = labels
+            .iter()
+            .map(|label| current.position(label).or(current.position(&base)).unwrap())
+            .collect();
+        let run_length = *self.granularity.run_length(dimension_name);
+        let mut run_lengths = self.granularity.run_lengths_for(&granularity);
+        run_lengths[idx] = 0;
+
+        let source = self.values().values();
+        let mut values = Vec::with_capacity(granularity.len());
+        granularity.for_each_cell(|index| {
+            let offset: usize = index.iter().zip(&run_lengths).map(|(i, r)| i * r).sum();
+            values.push(source[offset + positions[index[idx]] * run_length]);
+        });
+
+        Cow::Owned(Data::from_parts(granularity, Values::from(values)).with_metadata_from(self))
+    }
+}
+
+impl Override {
+    /// Creates an override, see `Data::with_override_in`, of data with the
+    /// granularity `granularity`.
+    fn new<V: Into<DimensionValue> + Clone>(
+        granularity: &Granularity,
+        dimension_name: &str,
+        scenario: &str,
+        selector: &[(&str, V)],
+        value: f64,
+    ) -> Result<Self> {
+        if granularity.dimension_index(dimension_name).is_some() {
+            return Err(Error::InvalidGranularity(format!(
+                "the data already has a '{}' dimension",
+                dimension_name
+            )));
+        }
+        if scenario == BASE_SCENARIO {
+            return Err(Error::InvalidGranularity(format!(
+                "the '{}' scenario cannot be overridden",
+                BASE_SCENARIO
+            )));
+        }
+        let mut resolved = Vec::with_capacity(selector.len());
+        for (name, value) in selector {
+            let value: DimensionValue = value.clone().into();
+            if !granularity.maybe_varies_by(name) {
+                return Err(Error::UnknownDimension(name.to_string()));
+            }
+            let values = granularity.dimension_values(name).unwrap();
+            if values.position(&value).is_none() {
+                return Err(Error::InvalidDimensionValue {
+                    kind: value.kind(),
+                    value: value.to_string(),
+                });
+            }
+            resolved.push((name.to_string(), value));
+        }
+        Ok(Self {
+            scenario: scenario.to_string(),
+            selector: resolved,
+            value,
+        })
+    }
+}
+
+/// Aligns the scenarios of `operands` so that every operand that varies by
+/// a scenario dimension has the same scenarios, skipping the dimension
+/// `except` if given.
+///
+/// The scenario dimensions are `scenario` and the dimension of the
+/// overrides of each operand.  One is only aligned if every operand that
+/// varies by it has a `base` value for it.  Returns an error if only some
+/// of them do, as the other operands cannot take on the missing scenarios.
+pub(crate) fn align_scenarios<'a>(
+    operands: &[&'a Data],
+    except: Option<&str>,
+) -> Result<Vec<Cow<'a, Data>>> {
+    let base = DimensionValue::from(BASE_SCENARIO);
+    let mut names: Vec<&str> = vec![SCENARIO_DIMENSION];
+    for operand in operands {
+        if let Storage::Scenarios(scenarios, _) = &operand.storage
+            && !names.contains(&scenarios.dimension.as_str())
+        {
+            names.push(&scenarios.dimension);
+        }
+    }
+    names.retain(|name| Some(*name) != except);
+
+    let mut aligned: Vec<Cow<'a, Data>> = operands.iter().map(|o| Cow::Borrowed(*o)).collect();
+    for name in names {
+        let varied: Vec<DimensionValues> = aligned
+            .iter()
+            .filter(|operand| operand.granularity.maybe_varies_by(name))
+            .map(|operand| operand.granularity.dimension_values(name).unwrap().clone())
+            .collect();
+        if varied.windows(2).all(|pair| pair[0] == pair[1]) {
+            continue;
+        }
+        let with_base = varied
+            .iter()
+            .filter(|values| values.position(&base).is_some())
+            .count();
+        if with_base == 0 {
+            // Not scenarios, broadcasting reports the conflict.
+            continue;
+        }
+        if with_base < varied.len() {
+            return Err(Error::UnalignedScenarios {
+                dimension: name.to_string(),
+            });
+        }
+
+        let mut labels: Vec<DimensionValue> = Vec::new();
+        for scenario in varied.iter().flat_map(|values| values.iter()) {
+            if !labels.contains(scenario) {
+                labels.push(scenario.clone());
+            }
+        }
+        for operand in aligned.iter_mut() {
+            *operand = match operand {
+                Cow::Borrowed(data) => data.expand_scenarios_to(name, &labels),
+                Cow::Owned(data) => {
+                    Cow::Owned(data.expand_scenarios_to(name, &labels).into_owned())
+                }
+            };
+        }
+    }
+    Ok(aligned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Expr,
+        operators::{BinaryOp, BroadcastPolicy, mul, try_binary_op},
+    };
+
+    #[test]
+    fn test_override_is_sparse_until_used() {
+        let price = Data::new("region".to_string(), vec!["EU", "US"], vec![1.0, 2.0])
+            .with_override("downside", &[("region", "EU")], 0.8)
+            .unwrap();
+        assert_eq!(price.stored_len(), 2);
+        assert_eq!(price.scenarios(), vec!["base", "downside"]);
+        // Every accessor sees the scenario dimension, not just the base values.
+        assert!(price.granularity().varies_by("scenario"));
+        let cells: Vec<f64> = price.iter_cells().map(|(_, value)| value).collect();
+        assert_eq!(cells, vec![1.0, 0.8, 2.0, 2.0]);
+        let eu = price.slice("region", "EU").unwrap().to_data();
+        assert_eq!(eu.values().values().to_vec(), vec![1.0, 0.8]);
+
+        let volume = Data::new("product".to_string(), vec!["A", "B"], vec![10.0, 20.0]);
+        let revenue = mul(&price, &volume);
+        assert!(revenue.granularity().varies_by("scenario"));
+        assert_eq!(
+            revenue.values().values().to_vec(),
+            vec![10.0, 8.0, 20.0, 20.0, 20.0, 16.0, 40.0, 40.0]
+        );
+    }
+
+    #[test]
+    fn test_later_overrides_take_priority() {
+        let data = Data::from_rows(
+            &["region", "product"],
+            [
+                (vec!["EU", "A"], 1.0),
+                (vec!["EU", "B"], 2.0),
+                (vec!["US", "A"], 3.0),
+                (vec!["US", "B"], 4.0),
+            ],
+        )
+        .unwrap()
+        .with_override("downside", &[("region", "EU")], 0.0)
+        .unwrap()
+        .with_override("downside", &[("region", "EU"), ("product", "A")], 9.0)
+        .unwrap();
+
+        assert_eq!(
+            data.values().values().to_vec(),
+            vec![1.0, 9.0, 3.0, 3.0, 2.0, 0.0, 4.0, 4.0]
+        );
+    }
+
+    #[test]
+    fn test_scenarios_are_aligned() {
+        let price = Data::new("region".to_string(), vec!["EU", "US"], vec![2.0, 3.0])
+            .with_override("downside", &[("region", "EU")], 1.0)
+            .unwrap();
+        let volume = Data::new("region".to_string(), vec!["EU", "US"], vec![10.0, 20.0])
+            .with_override("upside", &[("region", "US")], 30.0)
+            .unwrap();
+
+        let revenue = (Expr::from(&price) * &volume).evaluate().unwrap();
+        let scenarios = revenue.granularity().dimension_values("scenario").unwrap();
+        assert_eq!(scenarios.len(), 3);
+        assert_eq!(
+            revenue.values().values().to_vec(),
+            vec![20.0, 10.0, 20.0, 60.0, 60.0, 90.0]
+        );
+
+        // A result that already has the scenario dimension is aligned too.
+        let upside_only = Data::new("region".to_string(), vec!["EU", "US"], vec![1.0, 1.0])
+            .with_override("upside", &[("region", "EU")], 2.0)
+            .unwrap();
+        let total = mul(&revenue, &upside_only);
+        assert_eq!(
+            total.values().values().to_vec(),
+            vec![20.0, 10.0, 40.0, 60.0, 60.0, 90.0]
+        );
+    }
+
+    #[test]
+    fn test_invalid_override() {
+        let volume = Data::new("region".to_string(), vec!["EU", "US"], vec![5.0, 6.0]);
+        let result = volume
+            .clone()
+            .with_override("downside", &[("region", "APAC")], 4.0);
+        assert!(matches!(result, Err(Error::InvalidDimensionValue { .. })));
+        let result = volume.with_override("base", &[("region", "EU")], 4.0);
+        assert!(matches!(result, Err(Error::InvalidGranularity(_))));
+    }
+
+    #[test]
+    fn test_scenario_dimension_clash() {
+        let actual = Data::new("region".to_string(), vec!["EU", "US"], vec![1.0, 2.0]);
+        let budget = Data::new("region".to_string(), vec!["EU", "US"], vec![3.0, 4.0]);
+        let volume = Data::stack(&[("actual", &actual), ("budget", &budget)], "scenario").unwrap();
+        let price = Data::new("region".to_string(), vec!["EU", "US"], vec![10.0, 20.0]);
+
+        let downside = price
+            .clone()
+            .with_override("downside", &[("region", "EU")], 5.0)
+            .unwrap();
+        let policy = BroadcastPolicy::default();
+        let error = try_binary_op(BinaryOp::Mul, &volume, &downside, &policy).unwrap_err();
+        assert_eq!(
+            error,
+            Error::UnalignedScenarios {
+                dimension: "scenario".to_string()
+            }
+        );
+
+        // Choosing another dimension keeps the overrides apart from the stack.
+        let downside = price
+            .with_override_in("what_if", "downside", &[("region", "EU")], 5.0)
+            .unwrap();
+        assert_eq!(downside.scenarios(), vec!["base", "downside"]);
+        let revenue = mul(&volume, &downside);
+        assert!(revenue.granularity().varies_by("scenario"));
+        assert!(revenue.granularity().varies_by("what_if"));
+        assert_eq!(
+            revenue.values().values().to_vec(),
+            vec![10.0, 5.0, 30.0, 15.0, 40.0, 40.0, 80.0, 80.0]
+        );
+        let result = downside.with_override("upside", &[("region", "US")], 30.0);
+        assert!(matches!(result, Err(Error::InvalidGranularity(_))));
+    }
+
+    #[test]
+    fn test_other_dimensions_are_not_aligned() {
+        // A "base" region does not make "region" a scenario dimension.
+        let eu = Data::new("region".to_string(), vec!["base", "EU"], vec![1.0, 2.0]);
+        let us = Data::new("region".to_string(), vec!["base", "US"], vec![3.0, 4.0]);
+        let policy = BroadcastPolicy::default();
+        let error = try_binary_op(BinaryOp::Mul, &eu, &us, &policy).unwrap_err();
+        assert_eq!(
+            error,
+            Error::ConflictingDimensionValues("region".to_string())
+        );
+    }
+}
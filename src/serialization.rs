@@ -0,0 +1,289 @@
+//! Contains the `serde` implementations for `Data`.
+//!
+//! `Data` is serialized as its `Granularity` alongside a plain sequence
+//! of values.  Deserialization checks that the number of values matches
+//! the cell count implied by the run-lengths.  Sparse data instead leaves
+//! the values empty and writes only its stored cells, as the offset and
+//! value of each alongside the fill, so it is not densified.  The
+//! additivity of each dimension, the unit, the overrides and the valid
+//! combinations are optional so older documents can still be read.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+use crate::{
+    Data, Granularity,
+    aggregation::Additivity,
+    combinations::ValidCombinations,
+    data::Values,
+    error::{Error, Result},
+    scenario::Override,
+    sparse::{SparseValues, Stored},
+    unit::Unit,
+};
+
+#[derive(Serialize)]
+struct DataRef<'a> {
+    granularity: &'a Granularity,
+    values: &'a [f64],
+    sparse: Option<&'a SparseValues>,
+    additivity: &'a BTreeMap<String, Additivity>,
+    unit: &'a Option<Unit>,
+    scenario_dimension: Option<&'a str>,
+    overrides: &'a [Override],
+    valid_combinations: &'a [ValidCombinations],
+}
+
+#[derive(Deserialize)]
+struct DataRepr {
+    granularity: Granularity,
+    #[serde(deserialize_with = "deserialize_values")]
+    values: Vec<f64>,
+    #[serde(default)]
+    sparse: Option<SparseRepr>,
+    #[serde(default)]
+    additivity: BTreeMap<String, Additivity>,
+    #[serde(default)]
+    unit: Option<Unit>,
+    #[serde(default)]
+    scenario_dimension: Option<String>,
+    #[serde(default)]
+    overrides: Vec<Override>,
+    #[serde(default)]
+    valid_combinations: Vec<ValidCombinations>,
+}
+
+#[derive(Deserialize)]
+struct SparseRepr {
+    offsets: Vec<usize>,
+    #[serde(deserialize_with = "deserialize_values")]
+    values: Vec<f64>,
+    #[serde(deserialize_with = "deserialize_value")]
+    fill: f64,
+}
+
+/// Deserializes the values, reading `null` as `NaN` in human readable formats
+/// such as JSON, which have no other way to write `NaN`.
+fn deserialize_values<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<f64>, D::Error> {
+    if deserializer.is_human_readable() {
+        let values = Vec::<Option<f64>>::deserialize(deserializer)?;
+        Ok(values
+            .into_iter()
+            .map(|value| value.unwrap_or(f64::NAN))
+            .collect())
+    } else {
+        Vec::<f64>::deserialize(deserializer)
+    }
+}
+
+/// Deserializes a single value, reading `null` as `NaN` as for
+/// `deserialize_values`.
+fn deserialize_value<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<f64, D::Error> {
+    if deserializer.is_human_readable() {
+        Ok(Option::<f64>::deserialize(deserializer)?.unwrap_or(f64::NAN))
+    } else {
+        f64::deserialize(deserializer)
+    }
+}
+
+impl TryFrom<DataRepr> for Data {
+    type Error = Error;
+
+    fn try_from(repr: DataRepr) -> Result<Self> {
+        let expected = repr.granularity.len();
+        let mut data = match repr.sparse {
+            Some(sparse) if repr.values.is_empty() => {
+                let sparse =
+                    SparseValues::try_new(sparse.offsets, sparse.values, sparse.fill, expected)?;
+                Data::from_sparse(repr.granularity, sparse)
+            }
+            Some(_) => {
+                return Err(Error::LengthMismatch {
+                    expected: 0,
+                    actual: repr.values.len(),
+                });
+            }
+            None if repr.values.len() != expected => {
+                return Err(Error::LengthMismatch {
+                    expected,
+                    actual: repr.values.len(),
+                });
+            }
+            None => Data::from_parts(repr.granularity, Values::from(repr.values)),
+        };
+        data.additivity = repr.additivity;
+        data.unit = repr.unit;
+        data.with_overrides(repr.scenario_dimension.as_deref(), &repr.overrides)?
+            .with_each_valid_combinations(repr.valid_combinations)
+    }
+}
+
+impl Serialize for Data {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        // Overrides are stored alongside the values without the scenario
+        // dimension, rather than expanded.
+        let (base, overrides) = self.base_and_overrides();
+        let (values, sparse) = match base.stored() {
+            Stored::Dense(values) => (&values.values()[..], None),
+            Stored::Sparse(sparse) => (&[][..], Some(sparse)),
+        };
+        DataRef {
+            granularity: &base.granularity,
+            values,
+            sparse,
+            additivity: &self.additivity,
+            unit: &self.unit,
+            scenario_dimension: self.scenario_dimension(),
+            overrides,
+            valid_combinations: &self.valid_combinations,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Data {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let repr = DataRepr::deserialize(deserializer)?;
+        Data::try_from(repr).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Period, data::Storage};
+
+    #[test]
+    fn test_valid_combinations_round_trip() {
+        let data = Data::from_rows(
+            &["product", "region"],
+            [
+                (vec!["A", "EU"], 1.0),
+                (vec!["A", "US"], 2.0),
+                (vec!["B", "EU"], 3.0),
+                (vec!["B", "US"], 4.0),
+            ],
+        )
+        .unwrap()
+        .with_valid_combinations(&["product", "region"], [vec!["A", "EU"], vec!["B", "US"]])
+        .unwrap();
+        let json = serde_json::to_string(&data).unwrap();
+        let decoded: Data = serde_json::from_str(&json).unwrap();
+
+        assert!(decoded.has_valid_combinations());
+        assert_eq!(decoded.stored_len(), 2);
+        assert!(decoded.values().values()[1].is_nan());
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let months = vec![Period::monthly(2024, 3), Period::monthly(2024, 4)];
+        let data = Data::new("time".to_string(), months, vec![5.0, 6.0])
+            .with_unit(Unit::parse("EUR/h").unwrap())
+            .with_override("upside", &[("time", Period::monthly(2024, 4))], 4.0)
+            .unwrap();
+        let json = serde_json::to_string(&data).unwrap();
+        let decoded: Data = serde_json::from_str(&json).unwrap();
+
+        assert!(decoded.granularity() == data.granularity());
+        assert_eq!(decoded.values(), data.values());
+        assert_eq!(json.matches("\"EUR/h\"").count(), 1);
+        assert_eq!(decoded.unit(), data.unit());
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let data = Data::new("site".to_string(), vec!["Lyon", "Oslo"], vec![2.0, 3.0])
+            .with_additivity("site", Additivity::NonAdditive)
+            .with_override("hiring", &[("site", "Oslo")], 5.0)
+            .unwrap();
+        let bytes = postcard::to_stdvec(&data).unwrap();
+        let decoded: Data = postcard::from_bytes(&bytes).unwrap();
+
+        assert!(decoded.granularity() == data.granularity());
+        assert_eq!(decoded.values(), data.values());
+        assert_eq!(decoded.additivity("site"), Additivity::NonAdditive);
+        assert_eq!(decoded.scenarios(), vec!["base", "hiring"]);
+    }
+
+    #[test]
+    fn test_sparse_round_trip() {
+        let data =
+            Data::from_rows_sparse(&["region"], [(vec!["EU"], 1.0), (vec!["APAC"], 3.0)]).unwrap();
+        let json = serde_json::to_value(&data).unwrap();
+        assert_eq!(json["values"], serde_json::json!([]));
+        assert_eq!(json["sparse"]["fill"], serde_json::Value::Null);
+
+        let decoded: Data = serde_json::from_value(json).unwrap();
+        assert!(decoded.is_sparse());
+        assert_eq!(decoded.stored_len(), 2);
+
+        let bytes = postcard::to_stdvec(&data).unwrap();
+        let decoded: Data = postcard::from_bytes(&bytes).unwrap();
+        assert!(decoded.is_sparse());
+        let (stored, original) = (decoded.sparse().unwrap(), data.sparse().unwrap());
+        assert_eq!(stored.offsets, original.offsets);
+        assert_eq!(stored.values, original.values);
+
+        // Serializing does not cache a dense copy of the values.
+        assert!(matches!(&data.storage, Storage::Sparse(_, dense) if dense.get().is_none()));
+    }
+
+    #[test]
+    fn test_sparse_offsets_are_validated() {
+        let data = Data::from_rows_sparse(&["region"], [(vec!["EU"], 1.0)]).unwrap();
+        let mut json = serde_json::to_value(&data).unwrap();
+        json["sparse"]["offsets"][0] = 5.into();
+
+        let error = serde_json::from_value::<Data>(json).err().unwrap();
+        assert!(error.to_string().contains("sparse offsets"));
+    }
+
+    #[test]
+    fn test_scenario_dimension_round_trip() {
+        let data = Data::new("region".to_string(), vec!["EU", "US"], vec![1.0, 2.0])
+            .with_override_in("what_if", "downside", &[("region", "EU")], 0.5)
+            .unwrap();
+        let json = serde_json::to_string(&data).unwrap();
+        let decoded: Data = serde_json::from_str(&json).unwrap();
+
+        assert!(decoded.granularity().varies_by("what_if"));
+        assert_eq!(decoded.values(), data.values());
+    }
+
+    #[test]
+    fn test_additivity_is_optional() {
+        let data = Data::new("region".to_string(), vec!["EU", "US"], vec![1.0, 2.0])
+            .with_additivity("region", Additivity::SemiAdditive);
+        let mut json: serde_json::Value = serde_json::to_value(data).unwrap();
+        json.as_object_mut().unwrap().remove("additivity");
+
+        let decoded: Data = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded.additivity("region"), Additivity::Additive);
+    }
+
+    #[test]
+    fn test_length_is_validated() {
+        let data = Data::new("sku".to_string(), vec!["S1", "S2", "S3"], vec![1.0; 3]);
+        let mut json: serde_json::Value = serde_json::to_value(data).unwrap();
+        json["values"].as_array_mut().unwrap().pop();
+
+        let error = serde_json::from_value::<Data>(json).err().unwrap();
+        assert!(error.to_string().contains("Expected 3 values but found 2"));
+    }
+
+    #[test]
+    fn test_run_lengths_are_validated() {
+        let data = Data::new("sku".to_string(), vec!["S1", "S2"], vec![1.0, 2.0]);
+        let mut json: serde_json::Value = serde_json::to_value(data).unwrap();
+        json["granularity"]["flags"]["run_lengths"][0] = 7.into();
+
+        let error = serde_json::from_value::<Data>(json).err().unwrap();
+        assert!(error.to_string().contains("Invalid granularity"));
+    }
+}
@@ -0,0 +1,418 @@
+//! Contains the sparse storage of `Data`.
+//!
+//! Sparse data only stores the cells that differ from a fill value, as a
+//! list of offsets into the dense layout, e.g. a store × SKU matrix where
+//! each store stocks a few SKUs.  Operations that can keep the result sparse
+//! do so, such as multiplying by a scalar, reducing across a dimension or a
+//! binary operation whose result is the same wherever the sparse operand
+//! has its fill value.  Every other operation reads `Data::values`, which
+//! densifies the data the first time it is called.
+
+use std::{collections::BTreeMap, sync::OnceLock};
+
+use crate::{
+    Data, DimensionValue, Granularity,
+    aggregation::Aggregation,
+    data::{Storage, Values},
+    error::Result,
+    operators::BinaryOp,
+};
+
+/// The stored cells of sparse data, see `Storage::Sparse`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub(crate) struct SparseValues {
+    /// The offset of each stored cell within the dense layout, ascending.
+    pub(crate) offsets: Vec<usize>,
+
+    /// The value of each stored cell.
+    pub(crate) values: Vec<f64>,
+
+    /// The value of every cell that is not stored.
+    pub(crate) fill: f64,
+}
+
+impl SparseValues {
+    /// Creates sparse values from cells in any order, dropping any cell that
+    /// holds the fill value.
+    pub(crate) fn from_cells(mut cells: Vec<(usize, f64)>, fill: f64) -> Self {
+        cells.retain(|(_, value)| !same(*value, fill));
+        cells.sort_unstable_by_key(|(offset, _)| *offset);
+        let (offsets, values) = cells.into_iter().unzip();
+        Self {
+            offsets,
+            values,
+            fill,
+        }
+    }
+
+    /// Creates sparse values that were read back after being written,
+    /// checking that every offset is a cell of the `len` cells and that
+    /// there is a value for each.
+    #[cfg(feature = "serde")]
+    pub(crate) fn try_new(
+        offsets: Vec<usize>,
+        values: Vec<f64>,
+        fill: f64,
+        len: usize,
+    ) -> Result<Self> {
+        if values.len() != offsets.len() {
+            return Err(crate::Error::LengthMismatch {
+                expected: offsets.len(),
+                actual: values.len(),
+            });
+        }
+        let ascending = offsets.windows(2).all(|pair| pair[0] < pair[1]);
+        if !ascending || offsets.last().is_some_and(|offset| *offset >= len) {
+            return Err(crate::Error::InvalidGranularity(format!(
+                "the sparse offsets must ascend and be less than {}",
+                len
+            )));
+        }
+        Ok(Self {
+            offsets,
+            values,
+            fill,
+        })
+    }
+
+    pub(crate) fn to_dense(&self, len: usize) -> Values {
+        let mut values = vec![self.fill; len];
+        for (offset, value) in self.offsets.iter().zip(&self.values) {
+            values[*offset] = *value;
+        }
+        Values::from(values)
+    }
+
+    fn get(&self, offset: usize) -> f64 {
+        match self.offsets.binary_search(&offset) {
+            Ok(idx) => self.values[idx],
+            Err(_) => self.fill,
+        }
+    }
+}
+
+/// Whether two values are the same, treating every `NaN` as the same.
+fn same(a: f64, b: f64) -> bool {
+    a == b || (a.is_nan() && b.is_nan())
+}
+
+/// The values that are stored for data without overrides, see
+/// `Data::stored`.
+#[cfg(feature = "serde")]
+pub(crate) enum Stored<'a> {
+    Dense(&'a Values),
+    Sparse(&'a SparseValues),
+}
+
+impl Data {
+    pub(crate) fn from_sparse(granularity: Granularity, sparse: SparseValues) -> Self {
+        let mut data = Data::from_parts(granularity, Values::from(Vec::<f64>::new()));
+        data.storage = Storage::Sparse(sparse, OnceLock::new());
+        data
+    }
+
+    /// Creates a new piece of sparse data from rows in "long" format, see
+    /// `from_rows`, where the cells that do not appear in `rows` are `NaN`.
+    pub fn from_rows_sparse<V: Into<DimensionValue>>(
+        dimension_names: &[&str],
+        rows: impl IntoIterator<Item = (Vec<V>, f64)>,
+    ) -> Result<Self> {
+        let (granularity, cells) = Self::cells_from_rows(dimension_names, rows)?;
+        Ok(Self::from_sparse(
+            granularity,
+            SparseValues::from_cells(cells, f64::NAN),
+        ))
+    }
+
+    /// Converts the data to sparse storage, only storing the cells whose
+    /// value is not `fill`.
+    pub fn to_sparse(&self, fill: f64) -> Data {
+        let cells = self.values().values().iter().copied().enumerate().collect();
+        Self::from_sparse(
+            self.granularity.clone(),
+            SparseValues::from_cells(cells, fill),
+        )
+        .with_metadata_from(self)
+    }
+
+    /// Converts the data to dense storage.
+    pub fn to_dense(&self) -> Data {
+        Data::from_parts(self.granularity.clone(), self.values().clone()).with_metadata_from(self)
+    }
+
+    pub fn is_sparse(&self) -> bool {
+        matches!(self.storage, Storage::Sparse(..))
+    }
+
+    /// Returns the number of values that are stored, which for sparse data
+    /// excludes the cells that hold the fill value and for data with
+    /// overrides only counts the values without the scenario dimension.
+    pub fn stored_len(&self) -> usize {
+        match &self.storage {
+            Storage::Dense(values) => values.len(),
+            Storage::Sparse(sparse, _) => sparse.offsets.len(),
+            Storage::Scenarios(scenarios, _) => scenarios.base.stored_len(),
+        }
+    }
+
+    /// Returns the values that are stored, the base values for data with
+    /// overrides, without densifying sparse data.
+    #[cfg(feature = "serde")]
+    pub(crate) fn stored(&self) -> Stored<'_> {
+        match &self.storage {
+            Storage::Dense(values) => Stored::Dense(values),
+            Storage::Sparse(sparse, _) => Stored::Sparse(sparse),
+            Storage::Scenarios(scenarios, _) => scenarios.base.stored(),
+        }
+    }
+
+    pub(crate) fn sparse(&self) -> Option<&SparseValues> {
+        match &self.storage {
+            Storage::Sparse(sparse, _) => Some(sparse),
+            Storage::Dense(_) | Storage::Scenarios(..) => None,
+        }
+    }
+
+    /// Multiplies sparse data by `amount`, returning `None` if the data is
+    /// dense.
+    pub(crate) fn mul_scalar_sparse(&self, amount: f64) -> Option<Data> {
+        let sparse = self.sparse()?;
+        let cells = sparse
+            .offsets
+            .iter()
+            .zip(&sparse.values)
+            .map(|(offset, value)| (*offset, value * amount))
+            .collect();
+        let sparse = SparseValues::from_cells(cells, sparse.fill * amount);
+        Some(Self::from_sparse(self.granularity.clone(), sparse).with_metadata_from(self))
+    }
+
+    /// Aggregates sparse data across the dimension `dimension_name`, which it
+    /// varies by, returning `None` if the data is dense.
+    pub(crate) fn reduce_sparse(
+        &self,
+        dimension_name: &str,
+        aggregation: Aggregation,
+        granularity: &Granularity,
+    ) -> Option<Data> {
+        let sparse = self.sparse()?;
+        let idx = granularity.dimension_index(dimension_name).unwrap();
+        let count = self.granularity.dimension_values(dimension_name)?.len();
+        let run_lengths = granularity.run_lengths_for(granularity);
+        let index_of = self.index_of(granularity);
+
+        // The stored values of each cell of the result, by their position
+        // along the dimension.
+        let mut groups: BTreeMap<usize, Vec<(usize, f64)>> = BTreeMap::new();
+        for (offset, value) in sparse.offsets.iter().zip(&sparse.values) {
+            let mut index = index_of(*offset);
+            let position = std::mem::take(&mut index[idx]);
+            let offset: usize = index.iter().zip(&run_lengths).map(|(i, r)| i * r).sum();
+            groups.entry(offset).or_default().push((position, *value));
+        }
+
+        let aggregate = |stored: &[(usize, f64)]| {
+            let mut stored = stored.iter().peekable();
+            aggregation.aggregate_weighted((0..count).map(|position| {
+                match stored.next_if(|(p, _)| *p == position) {
+                    Some((_, value)) => (*value, 1.0),
+                    None => (sparse.fill, 1.0),
+                }
+            }))
+        };
+        let fill = aggregate(&[]);
+        let cells = groups
+            .iter()
+            .map(|(offset, stored)| (*offset, aggregate(stored)))
+            .collect();
+        let sparse = SparseValues::from_cells(cells, fill);
+        Some(Self::from_sparse(granularity.clone(), sparse).with_metadata_from(self))
+    }
+
+    /// Returns a function from the offset of a cell of `self` to its index
+    /// into each dimension of `granularity`, which has every dimension of
+    /// `self`.
+    fn index_of(&self, granularity: &Granularity) -> impl Fn(usize) -> Vec<usize> {
+        // The run-length and size in `self` of each dimension of `granularity`.
+        let layout: Vec<Option<(usize, usize)>> = granularity
+            .dimensions_with_flags()
+            .into_iter()
+            .map(|(name, values, _)| {
+                self.granularity
+                    .maybe_varies_by(&name)
+                    .then(|| (*self.granularity.run_length(&name), values.len()))
+            })
+            .collect();
+        move |offset| {
+            layout
+                .iter()
+                .map(|l| l.map_or(0, |(run_length, size)| (offset / run_length) % size))
+                .collect()
+        }
+    }
+}
+
+/// Performs the binary operation `op`, keeping the result sparse where
+/// possible.
+///
+/// Returns `None` if neither operand is sparse or the result would be dense,
+/// i.e. the result is not the same wherever a sparse operand has its fill
+/// value.
+///
+/// # Panics
+///
+/// If a dimension has different values in `lhs` and `rhs`.
+pub(crate) fn sparse_binary_op(op: BinaryOp, lhs: &Data, rhs: &Data) -> Option<Data> {
+    if !lhs.is_sparse() && !rhs.is_sparse() {
+        return None;
+    }
+    let granularity = &lhs.granularity.broadcast(&rhs.granularity);
+    match (lhs.sparse(), rhs.sparse()) {
+        (Some(l), Some(r)) if lhs.granularity == rhs.granularity => {
+            let fill = op.apply(l.fill, r.fill);
+            let mut offsets: Vec<usize> = l.offsets.iter().chain(&r.offsets).copied().collect();
+            offsets.sort_unstable();
+            offsets.dedup();
+            let cells = offsets
+                .into_iter()
+                .map(|offset| (offset, op.apply(l.get(offset), r.get(offset))))
+                .collect();
+            let sparse = SparseValues::from_cells(cells, fill);
+            Some(Data::from_sparse(granularity.clone(), sparse))
+        }
+        (Some(sparse), _) => spread(sparse, lhs, rhs, granularity, |s, o| op.apply(s, o)),
+        (None, Some(sparse)) => spread(sparse, rhs, lhs, granularity, |s, o| op.apply(o, s)),
+        (None, None) => None,
+    }
+}
+
+/// Applies `op` between the sparse data `data` and `other` by spreading each
+/// stored cell across the dimensions of `granularity` that `data` does not
+/// vary by.
+fn spread(
+    sparse: &SparseValues,
+    data: &Data,
+    other: &Data,
+    granularity: &Granularity,
+    op: impl Fn(f64, f64) -> f64,
+) -> Option<Data> {
+    let others = other.values().values();
+    let fill = op(sparse.fill, *others.first()?);
+    if others
+        .iter()
+        .any(|value| !same(op(sparse.fill, *value), fill))
+    {
+        return None;
+    }
+
+    let run_lengths = granularity.run_lengths_for(granularity);
+    let other_run_lengths = other.granularity.run_lengths_for(granularity);
+    // The dimensions to spread each stored cell across, and their sizes.
+    let spread: Vec<(usize, usize)> = granularity
+        .dimensions_with_flags()
+        .iter()
+        .enumerate()
+        .filter(|(_, (name, _, varies))| *varies && !data.granularity.maybe_varies_by(name))
+        .map(|(idx, (_, values, _))| (idx, values.len()))
+        .collect();
+    if spread.iter().any(|(_, size)| *size == 0) {
+        return Some(Data::from_sparse(
+            granularity.clone(),
+            SparseValues::from_cells(Vec::new(), fill),
+        ));
+    }
+
+    let index_of = data.index_of(granularity);
+    let mut cells = Vec::new();
+    for (offset, value) in sparse.offsets.iter().zip(&sparse.values) {
+        let mut index = index_of(*offset);
+        loop {
+            let at = |run_lengths: &[usize]| -> usize {
+                index.iter().zip(run_lengths).map(|(i, r)| i * r).sum()
+            };
+            cells.push((at(&run_lengths), op(*value, others[at(&other_run_lengths)])));
+
+            // Advances to the next combination of the spread dimensions.
+            let next = spread
+                .iter()
+                .rev()
+                .find(|(idx, size)| index[*idx] + 1 < *size);
+            let Some((next, _)) = next else {
+                break;
+            };
+            index[*next] += 1;
+            for (idx, _) in spread.iter().filter(|(idx, _)| idx > next) {
+                index[*idx] = 0;
+            }
+        }
+    }
+    let sparse = SparseValues::from_cells(cells, fill);
+    Some(Data::from_sparse(granularity.clone(), sparse))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operators::{mul, mul_scalar};
+
+    #[test]
+    fn test_sparse_round_trip() {
+        let sparse = Data::from_rows_sparse(&["store"], [(vec!["Paris"], 1.0)]).unwrap();
+        assert!(sparse.is_sparse());
+        assert_eq!(sparse.stored_len(), 1);
+
+        let stocked = Data::new(
+            "sku".to_string(),
+            vec!["SKU1", "SKU2", "SKU3", "SKU4"],
+            vec![0.0, 2.0, 0.0, 0.0],
+        )
+        .to_sparse(0.0);
+        assert_eq!(stocked.stored_len(), 1);
+        assert_eq!(stocked.values().len(), 4);
+        assert!(!stocked.to_dense().is_sparse());
+    }
+
+    #[test]
+    fn test_operators_preserve_sparsity() {
+        // Two stores that each stock one of three SKUs.
+        let stocked = Data::from_rows(
+            &["store", "sku"],
+            [
+                (vec!["Paris", "SKU1"], 1.0),
+                (vec!["Paris", "SKU2"], 0.0),
+                (vec!["Paris", "SKU3"], 0.0),
+                (vec!["Berlin", "SKU1"], 0.0),
+                (vec!["Berlin", "SKU2"], 2.0),
+                (vec!["Berlin", "SKU3"], 0.0),
+            ],
+        )
+        .unwrap()
+        .to_sparse(0.0);
+        let price = Data::new(
+            "sku".to_string(),
+            vec!["SKU1", "SKU2", "SKU3"],
+            vec![10.0, 20.0, 30.0],
+        );
+        let dense = mul(&stocked.to_dense(), &price);
+
+        let value = mul(&stocked, &price);
+        assert!(value.is_sparse());
+        assert_eq!(value.stored_len(), 2);
+        assert_eq!(value.values(), dense.values());
+
+        let doubled = mul_scalar(&value, 2.0);
+        assert!(doubled.is_sparse());
+
+        let by_store = value.reduce("sku", Aggregation::Sum).unwrap();
+        assert!(by_store.is_sparse());
+        let expected = dense.reduce("sku", Aggregation::Sum).unwrap();
+        assert_eq!(by_store.values(), expected.values());
+
+        // Adding a dense operand changes every cell, so the result is dense.
+        let marked_up =
+            crate::operators::try_binary_op(BinaryOp::Add, &stocked, &price, &Default::default())
+                .unwrap();
+        assert!(!marked_up.is_sparse());
+    }
+}
@@ -0,0 +1,367 @@
+//! Contains units of measure and the conversions between them.
+//!
+//! A `Unit` is a product of named base units raised to integer powers, so
+//! `USD/unit` multiplied by `unit` is `USD`.  Conversions are only known
+//! through factors registered in `UnitConversions`, e.g. `kUSD` to `USD`.
+
+use std::{collections::BTreeMap, collections::HashMap, fmt, str::FromStr};
+
+use crate::{
+    Data,
+    error::{Error, Result},
+    operators::BinaryOp,
+};
+
+/// A unit of measure, e.g. `USD`, `kWh` or `USD/unit`.
+///
+/// The unit with no base units is dimensionless.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "String", into = "String")
+)]
+pub struct Unit {
+    /// The power of each base unit, which is never zero.
+    powers: BTreeMap<String, i32>,
+}
+
+impl Unit {
+    /// Creates a unit made of the single base unit `name`.
+    pub fn base(name: &str) -> Self {
+        Self {
+            powers: BTreeMap::from([(name.to_string(), 1)]),
+        }
+    }
+
+    /// Creates a dimensionless unit.
+    pub fn dimensionless() -> Self {
+        Self::default()
+    }
+
+    pub fn is_dimensionless(&self) -> bool {
+        self.powers.is_empty()
+    }
+
+    /// Parses units such as `USD`, `USD/unit`, `kWh*h` or `m^2`.
+    ///
+    /// `1` is the dimensionless unit.
+    pub fn parse(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidUnit(s.to_string());
+        let mut unit = Self::default();
+        let mut sign = 1;
+        let mut rest = s.trim();
+        loop {
+            let end = rest.find(['*', '/']).unwrap_or(rest.len());
+            let term = rest[..end].trim();
+            let (name, power) = match term.split_once('^') {
+                Some((name, power)) => (name.trim(), power.trim().parse().map_err(|_| invalid())?),
+                None => (term, 1),
+            };
+            if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                return Err(invalid());
+            }
+            if name != "1" {
+                unit.multiply(name, sign * power);
+            }
+            if end == rest.len() {
+                return Ok(unit);
+            }
+            sign = if rest[end..].starts_with('/') { -1 } else { 1 };
+            rest = &rest[end + 1..];
+        }
+    }
+
+    /// Multiplies the unit by the base unit `name` raised to `power`.
+    fn multiply(&mut self, name: &str, power: i32) {
+        let entry = self.powers.entry(name.to_string()).or_default();
+        *entry += power;
+        if *entry == 0 {
+            self.powers.remove(name);
+        }
+    }
+
+    /// Returns the product of `self` and `other`.
+    pub fn mul(&self, other: &Unit) -> Unit {
+        let mut unit = self.clone();
+        for (name, power) in &other.powers {
+            unit.multiply(name, *power);
+        }
+        unit
+    }
+
+    /// Returns `self` divided by `other`.
+    pub fn div(&self, other: &Unit) -> Unit {
+        let mut unit = self.clone();
+        for (name, power) in &other.powers {
+            unit.multiply(name, -power);
+        }
+        unit
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let term = |name: &str, power: i32| match power {
+            1 => name.to_string(),
+            _ => format!("{}^{}", name, power),
+        };
+        let numerator: Vec<String> = self
+            .powers
+            .iter()
+            .filter(|(_, power)| **power > 0)
+            .map(|(name, power)| term(name, *power))
+            .collect();
+        if numerator.is_empty() {
+            write!(f, "1")?;
+        } else {
+            write!(f, "{}", numerator.join("*"))?;
+        }
+        for (name, power) in self.powers.iter().filter(|(_, power)| **power < 0) {
+            write!(f, "/{}", term(name, -power))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Unit {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl TryFrom<String> for Unit {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        Self::parse(&s)
+    }
+}
+
+impl From<Unit> for String {
+    fn from(unit: Unit) -> Self {
+        unit.to_string()
+    }
+}
+
+/// Combines the units of the operands of `op`, where `None` is an unknown
+/// unit.  The result of an operation with an unknown unit is unknown too,
+/// except that a comparison is always dimensionless.
+///
+/// Returns an error if `op` adds, subtracts or compares different units.
+pub(crate) fn combine_units(
+    op: BinaryOp,
+    lhs: Option<&Unit>,
+    rhs: Option<&Unit>,
+) -> Result<Option<Unit>> {
+    let (lhs, rhs) = match (lhs, rhs) {
+        (Some(lhs), Some(rhs)) => (lhs, rhs),
+        (lhs, rhs) if op.is_comparison() => {
+            return Ok(lhs.or(rhs).map(|_| Unit::default()));
+        }
+        _ => return Ok(None),
+    };
+    match op {
+        BinaryOp::Mul => Ok(Some(lhs.mul(rhs))),
+        BinaryOp::Div => Ok(Some(lhs.div(rhs))),
+        _ if lhs != rhs => Err(Error::UnitMismatch {
+            lhs: lhs.to_string(),
+            rhs: rhs.to_string(),
+        }),
+        _ if op.is_comparison() => Ok(Some(Unit::default())),
+        _ => Ok(Some(lhs.clone())),
+    }
+}
+
+/// Combines `unit` with a scalar operand of `op`, which is on the left hand
+/// side if `scalar_is_lhs`.
+///
+/// A scalar takes on `unit` when added, subtracted or compared, and is
+/// dimensionless when multiplied or divided, so it never mismatches.
+pub(crate) fn combine_with_scalar(
+    op: BinaryOp,
+    unit: Option<&Unit>,
+    scalar_is_lhs: bool,
+) -> Option<Unit> {
+    let unit = unit?;
+    match op {
+        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul => Some(unit.clone()),
+        BinaryOp::Div if scalar_is_lhs => Some(Unit::default().div(unit)),
+        BinaryOp::Div => Some(unit.clone()),
+        BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge | BinaryOp::Eq | BinaryOp::Ne => {
+            Some(Unit::default())
+        }
+    }
+}
+
+/// A registry of the factors used to convert between units.
+#[derive(Debug, Clone, Default)]
+pub struct UnitConversions {
+    factors: HashMap<(String, String), f64>,
+}
+
+impl UnitConversions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder type API for registering that one `from` is `factor` of `to`,
+    /// e.g. `register("kUSD", "USD", 1000.0)`.
+    ///
+    /// The inverse conversion is registered too.
+    pub fn register(mut self, from: &str, to: &str, factor: f64) -> Self {
+        self.factors
+            .insert((from.to_string(), to.to_string()), factor);
+        self.factors
+            .insert((to.to_string(), from.to_string()), 1.0 / factor);
+        self
+    }
+
+    /// Returns the factor that converts values in `from` to values in `to`.
+    ///
+    /// Compound units are converted base unit by base unit, so registering
+    /// `kUSD` to `USD` also converts `kUSD/unit` to `USD/unit`.
+    pub fn factor(&self, from: &Unit, to: &Unit) -> Result<f64> {
+        let unknown = || Error::UnknownConversion {
+            from: from.to_string(),
+            to: to.to_string(),
+        };
+        if let Some(factor) = self.factors.get(&(from.to_string(), to.to_string())) {
+            return Ok(*factor);
+        }
+        if from.powers.len() != to.powers.len() {
+            return Err(unknown());
+        }
+
+        let mut factor = 1.0;
+        let mut targets: Vec<(&String, &i32)> = to.powers.iter().collect();
+        for (name, power) in &from.powers {
+            let position = targets
+                .iter()
+                .position(|(target, target_power)| {
+                    *target_power == power
+                        && (*target == name
+                            || self
+                                .factors
+                                .contains_key(&(name.clone(), (*target).clone())))
+                })
+                .ok_or_else(unknown)?;
+            let (target, _) = targets.remove(position);
+            if target != name {
+                factor *= self.factors[&(name.clone(), target.clone())].powi(*power);
+            }
+        }
+        Ok(factor)
+    }
+}
+
+impl Data {
+    /// Builder type API for setting the unit of the values.
+    pub fn with_unit(mut self, unit: Unit) -> Self {
+        self.unit = Some(unit);
+        self
+    }
+
+    /// Returns the unit of the values, if known.
+    pub fn unit(&self) -> Option<&Unit> {
+        self.unit.as_ref()
+    }
+
+    /// Converts the values to `unit` using the factors in `conversions`.
+    ///
+    /// Returns an error if the data has no unit or there is no conversion.
+    pub fn convert(&self, unit: &Unit, conversions: &UnitConversions) -> Result<Data> {
+        let from = self.unit().ok_or_else(|| Error::MissingUnit {
+            to: unit.to_string(),
+        })?;
+        let factor = conversions.factor(from, unit)?;
+        Ok(crate::operators::mul_scalar(self, factor).with_unit(unit.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operators::{BroadcastPolicy, mul, try_binary_op};
+
+    fn unit(s: &str) -> Unit {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!(unit("USD/unit").to_string(), "USD/unit");
+        assert_eq!(unit("kWh * h / m^2").to_string(), "h*kWh/m^2");
+        assert_eq!(unit("USD/unit").mul(&unit("unit")), unit("USD"));
+        assert!(unit("1").is_dimensionless());
+        assert!(matches!(
+            Unit::parse("USD//unit"),
+            Err(Error::InvalidUnit(_))
+        ));
+    }
+
+    #[test]
+    fn test_operators_combine_units() {
+        let price = Data::new("product".to_string(), vec!["A", "B"], vec![2.0, 3.0])
+            .with_unit(unit("USD/unit"));
+        let volume = Data::new("region".to_string(), vec!["EU", "US"], vec![10.0, 100.0])
+            .with_unit(Unit::base("unit"));
+        let revenue = mul(&price, &volume);
+        assert_eq!(revenue.unit(), Some(&Unit::base("USD")));
+
+        let policy = BroadcastPolicy::default();
+        let error = try_binary_op(BinaryOp::Add, &revenue, &volume, &policy).unwrap_err();
+        assert_eq!(error.to_string(), "Units 'USD' and 'unit' do not match");
+
+        let per_unit = try_binary_op(BinaryOp::Div, &revenue, &volume, &policy).unwrap();
+        assert_eq!(per_unit.unit(), Some(&unit("USD/unit")));
+
+        // The unit of unitless data is unknown, so is not guessed.
+        let unitless = Data::new("region".to_string(), vec!["EU", "US"], vec![1.0, 2.0]);
+        let sum = try_binary_op(BinaryOp::Add, &unitless, &revenue, &policy).unwrap();
+        assert_eq!(sum.unit(), None);
+        let ratio = try_binary_op(BinaryOp::Div, &unitless, &revenue, &policy).unwrap();
+        assert_eq!(ratio.unit(), None);
+        let more = try_binary_op(BinaryOp::Gt, &unitless, &revenue, &policy).unwrap();
+        assert_eq!(more.unit(), Some(&Unit::default()));
+    }
+
+    #[test]
+    fn test_scalar_units() {
+        let usd = Unit::base("USD");
+        let scalar = |op, scalar_is_lhs| combine_with_scalar(op, Some(&usd), scalar_is_lhs);
+        assert_eq!(scalar(BinaryOp::Add, true), Some(usd.clone()));
+        assert_eq!(scalar(BinaryOp::Mul, false), Some(usd.clone()));
+        assert_eq!(scalar(BinaryOp::Div, true), Some(unit("1/USD")));
+        assert_eq!(scalar(BinaryOp::Lt, false), Some(Unit::default()));
+        assert_eq!(combine_with_scalar(BinaryOp::Add, None, true), None);
+    }
+
+    #[test]
+    fn test_convert() {
+        let conversions = UnitConversions::new().register("kUSD", "USD", 1000.0);
+        let cost =
+            Data::new("product".to_string(), vec!["A"], vec![2.5]).with_unit(unit("kUSD/unit"));
+
+        let converted = cost.convert(&unit("USD/unit"), &conversions).unwrap();
+        assert_eq!(converted.values().value(0), 2500.0);
+        assert_eq!(converted.unit(), Some(&unit("USD/unit")));
+
+        let back = converted.convert(&unit("kUSD/unit"), &conversions).unwrap();
+        assert_eq!(back.values().value(0), 2.5);
+        assert!(matches!(
+            cost.convert(&unit("EUR/unit"), &conversions),
+            Err(Error::UnknownConversion { .. })
+        ));
+
+        let unitless = Data::new("product".to_string(), vec!["A"], vec![2.5]);
+        assert_eq!(
+            unitless.convert(&unit("USD"), &conversions).unwrap_err(),
+            Error::MissingUnit {
+                to: "USD".to_string()
+            }
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! Contains views, zero copy slices of a piece of `Data`.
+//!
+//! A view references the values of its parent along with an offset and the
+//! stride of each dimension, taken from the run-lengths of the parent.
+//! Slicing a dimension only moves the offset and zeroes the stride of the
+//! dimension, so it never copies values however many dimensions the data
+//! varies by.  Operators accept views directly, see `operators::Operand`,
+//! and a view is only copied when it is turned back into `Data` and its
+//! values are not contiguous.
+
+use crate::{
+    Data, DimensionValue, Granularity,
+    combinations::ValidCombinations,
+    data::Values,
+    error::{Error, Result},
+};
+
+/// A slice of a piece of `Data` that references the values of its parent,
+/// see `Data::slice`.
+#[derive(Clone)]
+pub struct DataView<'a> {
+    /// The data that the view is a slice of.
+    pub(crate) parent: &'a Data,
+
+    /// The granularity of the view, the sliced dimensions are dropped.
+    pub(crate) granularity: Granularity,
+
+    /// The offset of the first value of the view within the parent.
+    pub(crate) offset: usize,
+
+    /// The stride within the parent of each dimension of `granularity`.
+    pub(crate) strides: Vec<usize>,
+
+    /// The value that each sliced dimension was sliced at.
+    pub(crate) selection: Vec<(String, DimensionValue)>,
+}
+
+impl Data {
+    /// Returns a view of the cells where the dimension `dimension_name` has
+    /// the value `value`, without copying any values.
+    ///
+    /// The view reads the dense values of the data, see `values`.  Returns an
+    /// error if the data does not have the dimension or the value.
+    pub fn slice<V: Into<DimensionValue>>(
+        &self,
+        dimension_name: &str,
+        value: V,
+    ) -> Result<DataView<'_>> {
+        self.view().slice(dimension_name, value)
+    }
+
+    /// Returns a view of the whole of the data.
+    pub fn view(&self) -> DataView<'_> {
+        DataView {
+            granularity: self.granularity.clone(),
+            offset: 0,
+            strides: self.granularity.flags().run_lengths().clone(),
+            selection: Vec::new(),
+            parent: self,
+        }
+    }
+}
+
+impl<'a> DataView<'a> {
+    /// Slices the view further, see `Data::slice`.
+    pub fn slice<V: Into<DimensionValue>>(
+        &self,
+        dimension_name: &str,
+        value: V,
+    ) -> Result<DataView<'a>> {
+        let value = value.into();
+        let idx = self
+            .granularity
+            .dimension_index(dimension_name)
+            .ok_or_else(|| Error::UnknownDimension(dimension_name.to_string()))?;
+        let position = self
+            .granularity
+            .dimension_values(dimension_name)
+            .and_then(|values| values.position(&value))
+            .ok_or_else(|| Error::InvalidDimensionValue {
+                kind: value.kind(),
+                value: value.to_string(),
+            })?;
+
+        let mut view = self.clone();
+        if view.granularity.varies_by(dimension_name) {
+            view.offset += position * view.strides[idx];
+            view.strides[idx] = 0;
+            view.granularity.drop(dimension_name);
+        }
+        view.selection.push((dimension_name.to_string(), value));
+        Ok(view)
+    }
+
+    pub fn granularity(&self) -> &Granularity {
+        &self.granularity
+    }
+
+    /// Returns the number of cells in the view.
+    pub fn len(&self) -> usize {
+        self.granularity.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the values of the parent, which the view indexes into.
+    pub(crate) fn parent_values(&self) -> &[f64] {
+        self.parent.values().values()
+    }
+
+    /// Returns the stride of each dimension of `granularity`, which has
+    /// every dimension of the view, or `0` where the view does not vary by
+    /// it.
+    pub(crate) fn strides_for(&self, granularity: &Granularity) -> Vec<usize> {
+        granularity
+            .dimensions_with_flags()
+            .iter()
+            .map(
+                |(name, _, _)| match self.granularity.dimension_index(name) {
+                    Some(idx) if self.granularity.varies_by(name) => self.strides[idx],
+                    _ => 0,
+                },
+            )
+            .collect()
+    }
+
+    /// Returns the parent if the view covers the whole of it.
+    pub(crate) fn as_data(&self) -> Option<&Data> {
+        self.selection.is_empty().then_some(self.parent)
+    }
+
+    /// Copies the meta-data of the parent onto `data`, only keeping the
+    /// valid combinations that hold at the values the view was sliced at.
+    pub(crate) fn with_metadata(&self, data: Data) -> Data {
+        let mut data = data.with_metadata_from(self.parent);
+        data.valid_combinations = self.valid_combinations();
+        data
+    }
+
+    /// Returns the valid combinations of the parent that hold at the values
+    /// the view was sliced at.
+    pub(crate) fn valid_combinations(&self) -> Vec<ValidCombinations> {
+        let mut valid = self.parent.valid_combinations.clone();
+        for (name, value) in &self.selection {
+            valid = valid.iter().filter_map(|v| v.at(name, value)).collect();
+        }
+        valid
+    }
+
+    /// Turns the view into `Data`, which only copies the values if they are
+    /// not contiguous within the parent.
+    pub fn to_data(&self) -> Data {
+        if let Some(data) = self.as_data() {
+            return data.clone();
+        }
+        let contiguous = self.strides_for(&self.granularity)
+            == self.granularity.run_lengths_for(&self.granularity);
+        let values = if contiguous {
+            self.parent.values().slice(self.offset, self.len())
+        } else {
+            let parent = self.parent_values();
+            let mut values = Vec::with_capacity(self.len());
+            self.granularity.for_each_cell(|index| {
+                let offset: usize = index.iter().zip(&self.strides).map(|(i, s)| i * s).sum();
+                values.push(parent[self.offset + offset]);
+            });
+            Values::from(values)
+        };
+        self.with_metadata(Data::from_parts(self.granularity.clone(), values))
+    }
+}
+
+impl From<DataView<'_>> for Data {
+    fn from(view: DataView<'_>) -> Self {
+        view.to_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operators::mul;
+
+    #[test]
+    fn test_slice_does_not_copy() {
+        let sales = Data::from_rows(
+            &["region", "product"],
+            [
+                (vec!["EU", "A"], 1.0),
+                (vec!["EU", "B"], 2.0),
+                (vec!["EU", "C"], 3.0),
+                (vec!["US", "A"], 4.0),
+                (vec!["US", "B"], 5.0),
+                (vec!["US", "C"], 6.0),
+            ],
+        )
+        .unwrap();
+        let us = sales.slice("region", "US").unwrap();
+        assert_eq!(us.len(), 3);
+        let data = us.to_data();
+        assert_eq!(data.values().values().to_vec(), vec![4.0, 5.0, 6.0]);
+        // The outermost dimension is contiguous so the buffer is shared.
+        assert_eq!(
+            data.values().values().as_ptr(),
+            sales.values().values()[3..].as_ptr()
+        );
+
+        let b = sales.slice("product", "B").unwrap();
+        assert_eq!(b.to_data().values().values().to_vec(), vec![2.0, 5.0]);
+        let us_b = b.slice("region", "US").unwrap().to_data();
+        assert_eq!(us_b.values().values().to_vec(), vec![5.0]);
+
+        assert!(matches!(
+            sales.slice("region", "APAC"),
+            Err(Error::InvalidDimensionValue { .. })
+        ));
+    }
+
+    #[test]
+    fn test_operators_accept_views() {
+        let volume = Data::from_rows(
+            &["region", "product"],
+            [
+                (vec!["EU", "A"], 7.0),
+                (vec!["EU", "B"], 8.0),
+                (vec!["US", "A"], 1.0),
+                (vec!["US", "B"], 2.0),
+                (vec!["APAC", "A"], 4.0),
+                (vec!["APAC", "B"], 3.0),
+            ],
+        )
+        .unwrap();
+        let b = volume.slice("product", "B").unwrap();
+        let price = Data::new(
+            "region".to_string(),
+            vec!["EU", "US", "APAC"],
+            vec![10.0, 100.0, 1.0],
+        );
+
+        let revenue = mul(&b, &price);
+        assert_eq!(revenue.values().values().to_vec(), vec![80.0, 200.0, 3.0]);
+        assert_eq!(revenue.values(), mul(&b.to_data(), &price).values());
+    }
+}